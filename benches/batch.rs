@@ -0,0 +1,90 @@
+//! Parsing batches of 1,000 small torrents, from an uploaded archive and from a store scan.
+//!
+//! Each batch is timed with a single parse worker and with one per core, so it shows what
+//! the bounded pool gains over parsing one torrent after the other. Run with
+//! `cargo bench --bench batch`.
+
+mod common;
+
+use common::{bench, request, upload, Server, Value};
+
+const TORRENTS: usize = 1_000;
+
+/// A tar archive of `torrents`, named after their index.
+fn archive(torrents: &[Vec<u8>]) -> Vec<u8> {
+    let mut archive = tar::Builder::new(Vec::new());
+    for (i, torrent) in torrents.iter().enumerate() {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(torrent.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive
+            .append_data(&mut header, format!("{i}.torrent"), torrent.as_slice())
+            .unwrap();
+    }
+    archive.into_inner().unwrap()
+}
+
+fn main() {
+    let torrents: Vec<Vec<u8>> = (0..TORRENTS)
+        .map(|i| {
+            common::torrent(
+                &format!("small-{i}"),
+                64,
+                [(
+                    "announce",
+                    Value::str("udp://tracker.example:1337/announce"),
+                )],
+            )
+        })
+        .collect();
+    let archive = archive(&torrents);
+
+    let store =
+        std::env::temp_dir().join(format!("torrent-inspector-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&store).unwrap();
+    let store_dir = store.to_str().unwrap();
+    {
+        let _server = Server::start(&[("STORE_DIR", store_dir)]);
+        for torrent in &torrents {
+            assert_eq!(upload("/torrent", &[("file", torrent)]).status, 200);
+        }
+    }
+
+    let cores = std::thread::available_parallelism().map_or(1, usize::from);
+    let mut worker_counts = vec![1, cores];
+    worker_counts.dedup();
+    for workers in worker_counts {
+        let workers_env = workers.to_string();
+        let _server = Server::start(&[
+            ("STORE_DIR", store_dir),
+            ("PARSE_WORKERS", &workers_env),
+            ("DETERMINISTIC", "1"),
+        ]);
+        let archived = bench(
+            &format!("archive of {TORRENTS}, {workers} workers"),
+            10,
+            || {
+                let response = upload("/torrents/archive", &[("file", &archive)]);
+                assert_eq!(response.status, 200);
+            },
+        );
+        let scanned = bench(
+            &format!("scan of {TORRENTS}, {workers} workers"),
+            10,
+            || {
+                let response = request("GET", "/torrents/scan", &[], &[]);
+                assert_eq!(response.status, 200);
+                let events = String::from_utf8(response.body).unwrap();
+                assert!(events.contains(&format!("\"parsed\":{TORRENTS}")));
+            },
+        );
+        println!(
+            "  per torrent: {:.1} us from the archive, {:.1} us scanning",
+            archived.as_secs_f64() * 1e6 / TORRENTS as f64,
+            scanned.as_secs_f64() * 1e6 / TORRENTS as f64,
+        );
+    }
+
+    let _ = std::fs::remove_dir_all(&store);
+}
@@ -0,0 +1,265 @@
+//! Helpers shared by the benchmarks, which drive the built binary as the server or the CLI.
+//!
+//! The crate has no library target, so this is the only way in. Each benchmark prints the
+//! median of its runs rather than going through a harness.
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The binary under benchmark.
+pub const BINARY: &str = env!("CARGO_BIN_EXE_torrent-inspector");
+
+/// The address the server always listens on.
+const ADDR: &str = "127.0.0.1:3000";
+
+/// A bencoded value, to build synthetic torrents with.
+pub enum Value {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    Dict(BTreeMap<Vec<u8>, Value>),
+}
+
+impl Value {
+    pub fn str(value: &str) -> Value {
+        Value::Bytes(value.as_bytes().to_vec())
+    }
+
+    pub fn dict<const N: usize>(entries: [(&str, Value); N]) -> Value {
+        Value::Dict(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.as_bytes().to_vec(), value))
+                .collect(),
+        )
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Int(value) => out.extend(format!("i{value}e").as_bytes()),
+            Value::Bytes(bytes) => {
+                out.extend(format!("{}:", bytes.len()).as_bytes());
+                out.extend(bytes);
+            }
+            Value::List(values) => {
+                out.push(b'l');
+                values.iter().for_each(|value| value.encode_into(out));
+                out.push(b'e');
+            }
+            Value::Dict(entries) => {
+                out.push(b'd');
+                for (key, value) in entries {
+                    Value::Bytes(key.clone()).encode_into(out);
+                    value.encode_into(out);
+                }
+                out.push(b'e');
+            }
+        }
+    }
+}
+
+/// A single file torrent of `pieces` made up piece hashes, with the given extra top-level keys.
+pub fn torrent<const N: usize>(name: &str, pieces: usize, extra: [(&str, Value); N]) -> Vec<u8> {
+    let piece_length = 16384;
+    let mut torrent = Value::dict(extra);
+    let Value::Dict(entries) = &mut torrent else {
+        unreachable!()
+    };
+    let info = Value::dict([
+        ("name", Value::str(name)),
+        ("piece length", Value::Int(piece_length)),
+        ("length", Value::Int(pieces as i64 * piece_length)),
+        (
+            "pieces",
+            Value::Bytes((0..pieces * 20).map(|i| (i % 251) as u8).collect()),
+        ),
+    ]);
+    entries.insert(b"info".to_vec(), info);
+    torrent.encode()
+}
+
+/// A running server, killed on drop.
+pub struct Server {
+    child: Child,
+}
+
+impl Server {
+    /// Starts the server with the given environment, waiting until it accepts connections.
+    pub fn start(env: &[(&str, &str)]) -> Server {
+        let child = Command::new(BINARY)
+            .envs(env.iter().copied())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to start the server");
+        let server = Server { child };
+        let started = Instant::now();
+        while TcpStream::connect(ADDR).is_err() {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "the server did not start listening on {ADDR}, is something else using it?"
+            );
+            thread::sleep(Duration::from_millis(20));
+        }
+        server
+    }
+
+    /// The most memory the server has had resident so far, in bytes.
+    pub fn peak_rss(&self) -> u64 {
+        let status = std::fs::read_to_string(format!("/proc/{}/status", self.child.id()))
+            .expect("reading the peak RSS requires Linux");
+        let kib: u64 = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmHWM:"))
+            .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+            .expect("no VmHWM in /proc/<pid>/status");
+        kib * 1024
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A response, with the header names lowercased.
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Makes one HTTP/1.1 request on a fresh connection, reading the response to the end.
+pub fn request(method: &str, path: &str, headers: &[(&str, &str)], body: &[u8]) -> Response {
+    let mut stream = TcpStream::connect(ADDR).expect("failed to connect to the server");
+    let mut head = format!(
+        "{method} {path} HTTP/1.1\r\nhost: {ADDR}\r\nconnection: close\r\ncontent-length: {}\r\n",
+        body.len()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).unwrap();
+    stream.write_all(body).unwrap();
+
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).unwrap();
+    let split = raw
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .expect("no end of headers in the response");
+    let head = String::from_utf8_lossy(&raw[..split]).into_owned();
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|status| status.parse().ok())
+        .expect("no status line in the response");
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    let mut body = raw[split + 4..].to_vec();
+    if headers
+        .iter()
+        .any(|(name, value)| name == "transfer-encoding" && value == "chunked")
+    {
+        body = dechunk(&body);
+    }
+    Response {
+        status,
+        headers,
+        body,
+    }
+}
+
+fn dechunk(mut data: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = data.windows(2).position(|w| w == b"\r\n").unwrap();
+        let size = usize::from_str_radix(std::str::from_utf8(&data[..line_end]).unwrap(), 16)
+            .expect("bad chunk size");
+        if size == 0 {
+            return body;
+        }
+        body.extend(&data[line_end + 2..line_end + 2 + size]);
+        data = &data[line_end + 2 + size + 2..];
+    }
+}
+
+/// POSTs the files as a multipart upload, each under the field name given with it.
+pub fn upload(path: &str, files: &[(&str, &[u8])]) -> Response {
+    const BOUNDARY: &str = "torrent-inspector-bench";
+    let mut body = Vec::new();
+    for (index, (field, data)) in files.iter().enumerate() {
+        body.extend(
+            format!(
+                "--{BOUNDARY}\r\ncontent-disposition: form-data; name=\"{field}\"; \
+                 filename=\"{index}.torrent\"\r\n\r\n"
+            )
+            .as_bytes(),
+        );
+        body.extend(*data);
+        body.extend(b"\r\n");
+    }
+    body.extend(format!("--{BOUNDARY}--\r\n").as_bytes());
+    let content_type = format!("multipart/form-data; boundary={BOUNDARY}");
+    request("POST", path, &[("content-type", &content_type)], &body)
+}
+
+/// Runs `f` `runs` times and prints the median, returning it.
+pub fn bench(label: &str, runs: usize, mut f: impl FnMut()) -> Duration {
+    f();
+    let mut times: Vec<Duration> = (0..runs)
+        .map(|_| {
+            let started = Instant::now();
+            f();
+            started.elapsed()
+        })
+        .collect();
+    times.sort();
+    let median = times[runs / 2];
+    println!(
+        "{label}: {:.2} ms median of {runs}",
+        median.as_secs_f64() * 1e3
+    );
+    median
+}
+
+/// Inspects the torrent at `path` with the CLI.
+pub fn inspect_cli(path: &Path) {
+    let output = Command::new(BINARY)
+        .arg(path)
+        .stdout(Stdio::null())
+        .output()
+        .expect("failed to run the CLI");
+    assert!(
+        output.status.success(),
+        "the CLI failed on {}",
+        path.display()
+    );
+}
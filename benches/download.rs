@@ -0,0 +1,57 @@
+//! Downloading a 50 MiB saved metafile, which is streamed from disk rather than read whole.
+//!
+//! Prints the time per download and how much the server's peak RSS grows while serving it,
+//! which stays far below the size of the file. Run with `cargo bench --bench download`.
+
+mod common;
+
+use common::{bench, request, upload, Server};
+
+/// Pieces for a metafile of just over 50 MiB.
+const PIECES: usize = 50 * 1024 * 1024 / 20;
+
+fn main() {
+    let store =
+        std::env::temp_dir().join(format!("torrent-inspector-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&store).unwrap();
+    let store_dir = store.to_str().unwrap();
+    let torrent = common::torrent("large", PIECES, []);
+
+    // Saved by a server of its own, so that the upload does not count towards the peak.
+    let info_hash = {
+        let _server = Server::start(&[("STORE_DIR", store_dir)]);
+        let response = upload("/torrent", &[("file", &torrent)]);
+        assert_eq!(response.status, 200);
+        let body = String::from_utf8(response.body).unwrap();
+        let start = body
+            .find("\"v1\":\"")
+            .expect("no v1 info-hash in the response")
+            + 6;
+        body[start..start + 40].to_string()
+    };
+
+    let server = Server::start(&[("STORE_DIR", store_dir)]);
+    let before = server.peak_rss();
+    let path = format!("/torrent/{info_hash}");
+    bench(
+        &format!(
+            "download, {:.1} MiB",
+            torrent.len() as f64 / (1 << 20) as f64
+        ),
+        5,
+        || {
+            let response = request("GET", &path, &[], &[]);
+            assert_eq!(response.status, 200);
+            assert_eq!(response.body.len(), torrent.len());
+        },
+    );
+    let after = server.peak_rss();
+    println!(
+        "  peak RSS grew by {:.1} MiB, for a {:.1} MiB file",
+        (after - before) as f64 / (1 << 20) as f64,
+        torrent.len() as f64 / (1 << 20) as f64,
+    );
+
+    drop(server);
+    let _ = std::fs::remove_dir_all(&store);
+}
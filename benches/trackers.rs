@@ -0,0 +1,58 @@
+//! Tracker de-duplication on torrents with thousands of trackers, in inspections and merges.
+//!
+//! Every size is timed, so it shows whether the time per tracker stays flat as the lists grow,
+//! which it would not with quadratic de-duplication. Run with `cargo bench --bench trackers`.
+
+mod common;
+
+use common::{bench, upload, Server, Value};
+
+/// A torrent whose **announce-list** has `entries` single-tracker tiers, 60% of them distinct.
+fn torrent(entries: usize, offset: usize) -> Vec<u8> {
+    let distinct = entries * 3 / 5;
+    let tiers = (0..entries)
+        .map(|i| {
+            let tracker = format!(
+                "udp://tracker-{}.example:1337/announce",
+                (i + offset) % distinct
+            );
+            Value::List(vec![Value::str(&tracker)])
+        })
+        .collect();
+    common::torrent(
+        "trackers",
+        16,
+        [
+            (
+                "announce",
+                Value::str("udp://tracker-0.example:1337/announce"),
+            ),
+            ("announce-list", Value::List(tiers)),
+        ],
+    )
+}
+
+fn main() {
+    let _server = Server::start(&[("DETERMINISTIC", "1")]);
+    for entries in [1_000, 5_000, 50_000] {
+        let first = torrent(entries, 0);
+        let second = torrent(entries, entries / 2);
+
+        let inspected = bench(&format!("inspect, {entries} trackers"), 10, || {
+            let response = upload("/torrent", &[("file", &first)]);
+            assert_eq!(response.status, 200);
+        });
+        let merged = bench(&format!("merge, 2 x {entries} trackers"), 10, || {
+            let response = upload(
+                "/torrent/merge-trackers",
+                &[("file", &first), ("file", &second)],
+            );
+            assert_eq!(response.status, 200);
+        });
+        println!(
+            "  per tracker: {:.2} us inspecting, {:.2} us merging",
+            inspected.as_secs_f64() * 1e6 / entries as f64,
+            merged.as_secs_f64() * 1e6 / (2 * entries) as f64,
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Reading the torrents out of zip and tar archives.
+
+use std::fmt;
+use std::io::{Cursor, Read};
+
+/// Why the torrents could not be read out of an archive.
+#[derive(Debug)]
+pub(crate) enum ArchiveError {
+    /// Neither a zip nor a tar archive.
+    UnsupportedFormat,
+    TooManyEntries {
+        limit: usize,
+    },
+    /// The torrents in the archive add up to more than the configured limit.
+    SizeLimitExceeded {
+        limit: u64,
+    },
+    Corrupt,
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::UnsupportedFormat => {
+                write!(f, "Upload is neither a zip nor a tar archive")
+            }
+            ArchiveError::TooManyEntries { limit } => {
+                write!(f, "Archive has more than {limit} entries")
+            }
+            ArchiveError::SizeLimitExceeded { limit } => {
+                write!(
+                    f,
+                    "Torrents in the archive add up to more than {limit} bytes"
+                )
+            }
+            ArchiveError::Corrupt => write!(f, "Failed to read archive"),
+        }
+    }
+}
+
+/// Whether the data starts with the zip local file header signature.
+fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(b"PK\x03\x04")
+}
+
+/// Whether the data has the POSIX tar magic in its first header.
+fn is_tar(data: &[u8]) -> bool {
+    data.get(257..262) == Some(b"ustar")
+}
+
+/// Extracts every `.torrent` file of a zip or tar archive, along with its path in the archive.
+///
+/// At most `max_entries` entries are looked at, of any kind, and reading stops as soon as
+/// the extracted torrents grow past `max_bytes` in total.
+pub(crate) fn torrents(
+    data: &[u8],
+    max_entries: usize,
+    max_bytes: u64,
+) -> Result<Vec<(String, Vec<u8>)>, ArchiveError> {
+    let mut budget = Budget {
+        entries: max_entries,
+        bytes: max_bytes,
+        max_entries,
+        max_bytes,
+    };
+    let mut torrents = Vec::new();
+    if is_zip(data) {
+        let mut archive =
+            zip::ZipArchive::new(Cursor::new(data)).map_err(|_| ArchiveError::Corrupt)?;
+        for index in 0..archive.len() {
+            budget.entry()?;
+            let file = archive.by_index(index).map_err(|_| ArchiveError::Corrupt)?;
+            if file.is_file() && is_torrent(file.name()) {
+                let path = file.name().to_string();
+                torrents.push((path, budget.read(file)?));
+            }
+        }
+    } else if is_tar(data) {
+        let mut archive = tar::Archive::new(data);
+        for entry in archive.entries().map_err(|_| ArchiveError::Corrupt)? {
+            budget.entry()?;
+            let entry = entry.map_err(|_| ArchiveError::Corrupt)?;
+            let path = entry
+                .path()
+                .map_err(|_| ArchiveError::Corrupt)?
+                .to_string_lossy()
+                .into_owned();
+            if entry.header().entry_type().is_file() && is_torrent(&path) {
+                torrents.push((path, budget.read(entry)?));
+            }
+        }
+    } else {
+        return Err(ArchiveError::UnsupportedFormat);
+    }
+    Ok(torrents)
+}
+
+fn is_torrent(path: &str) -> bool {
+    path.to_ascii_lowercase().ends_with(".torrent")
+}
+
+/// What is left of the limits while going through an archive.
+struct Budget {
+    entries: usize,
+    bytes: u64,
+    max_entries: usize,
+    max_bytes: u64,
+}
+
+impl Budget {
+    fn entry(&mut self) -> Result<(), ArchiveError> {
+        self.entries = self
+            .entries
+            .checked_sub(1)
+            .ok_or(ArchiveError::TooManyEntries {
+                limit: self.max_entries,
+            })?;
+        Ok(())
+    }
+
+    /// Reads an entry, without ever holding more than the remaining bytes of it in memory.
+    fn read(&mut self, entry: impl Read) -> Result<Vec<u8>, ArchiveError> {
+        let mut data = Vec::new();
+        entry
+            .take(self.bytes.saturating_add(1))
+            .read_to_end(&mut data)
+            .map_err(|_| ArchiveError::Corrupt)?;
+        self.bytes =
+            self.bytes
+                .checked_sub(data.len() as u64)
+                .ok_or(ArchiveError::SizeLimitExceeded {
+                    limit: self.max_bytes,
+                })?;
+        Ok(data)
+    }
+}
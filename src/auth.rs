@@ -0,0 +1,50 @@
+//! Bearer tokens for the endpoints that change what is saved.
+
+use crate::config::Config;
+use crate::error::{AppError, ErrorCode};
+use axum::body::Body;
+use axum::http::header::AUTHORIZATION;
+use axum::http::{HeaderMap, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::sync::Arc;
+
+/// Whether the `Authorization` header carries `token` as a bearer token.
+///
+/// Compared in constant time, so the token cannot be guessed byte by byte from response times.
+pub(crate) fn is_authorized(headers: &HeaderMap, token: &str) -> bool {
+    let Some(given) = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Answers 401 unless the request carries `ADMIN_TOKEN` as a bearer token,
+/// for the routes [`Config::requires_token`] picks.
+pub(crate) async fn require_token(request: Request<Body>, next: Next<Body>) -> Response {
+    let authorized = request
+        .extensions()
+        .get::<Arc<Config>>()
+        .and_then(|config| config.admin_token.as_ref())
+        .is_some_and(|token| is_authorized(request.headers(), token));
+    if !authorized {
+        return (
+            [("www-authenticate", "Bearer")],
+            AppError::new(
+                ErrorCode::Unauthorized,
+                "This endpoint requires the admin token as a bearer token",
+            ),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
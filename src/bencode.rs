@@ -0,0 +1,153 @@
+//! A minimal bencode scanner working directly on the raw bytes.
+//!
+//! `serde_bencode` gives us typed values, but it throws away where those values
+//! were located in the original file. Some things (like the info-hash) must be
+//! computed over the exact bytes of the original file, so we walk the structure ourselves.
+
+use std::ops::Range;
+
+/// Nesting limit for lists and dictionaries, so that a crafted file cannot blow up the stack.
+const MAX_DEPTH: usize = 64;
+
+/// Returns the end offset of the bencode value starting at `data[start]`.
+fn value_end(data: &[u8], start: usize, depth: usize) -> Option<usize> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    match *data.get(start)? {
+        b'i' => {
+            let end = start + 1 + data[start + 1..].iter().position(|&b| b == b'e')?;
+            Some(end + 1)
+        }
+        b'l' | b'd' => {
+            let mut pos = start + 1;
+            while *data.get(pos)? != b'e' {
+                pos = value_end(data, pos, depth + 1)?;
+            }
+            Some(pos + 1)
+        }
+        b'0'..=b'9' => string_at(data, start).map(|range| range.end),
+        _ => None,
+    }
+}
+
+/// Finds every integer in `data` that is not canonically encoded, as its offset and literal.
+///
+/// Bencode allows neither leading zeros (`i03e`) nor negative zero (`i-0e`), although
+/// `serde_bencode` reads both without complaint. `None` if `data` is not bencode at all.
+pub(crate) fn noncanonical_integers(data: &[u8]) -> Option<Vec<(usize, String)>> {
+    fn scan(
+        data: &[u8],
+        start: usize,
+        depth: usize,
+        found: &mut Vec<(usize, String)>,
+    ) -> Option<usize> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        match *data.get(start)? {
+            b'i' => {
+                let end = value_end(data, start, depth)?;
+                let digits = &data[start + 1..end - 1];
+                let magnitude = digits.strip_prefix(b"-").unwrap_or(digits);
+                if magnitude.starts_with(b"0") && digits != b"0" {
+                    found.push((
+                        start,
+                        String::from_utf8_lossy(&data[start..end]).into_owned(),
+                    ));
+                }
+                Some(end)
+            }
+            b'l' | b'd' => {
+                let mut pos = start + 1;
+                while *data.get(pos)? != b'e' {
+                    pos = scan(data, pos, depth + 1, found)?;
+                }
+                Some(pos + 1)
+            }
+            _ => value_end(data, start, depth),
+        }
+    }
+
+    let mut found = Vec::new();
+    scan(data, 0, 1, &mut found)?;
+    Some(found)
+}
+
+/// Returns the range of the string content for the byte string starting at `data[start]`.
+fn string_at(data: &[u8], start: usize) -> Option<Range<usize>> {
+    let colon = start + data[start..].iter().position(|&b| b == b':')?;
+    let len: usize = std::str::from_utf8(&data[start..colon])
+        .ok()?
+        .parse()
+        .ok()?;
+    let end = colon.checked_add(1)?.checked_add(len)?;
+    if end > data.len() {
+        return None;
+    }
+    Some(colon + 1..end)
+}
+
+/// Lists the entries of the dictionary starting at `data[0]`,
+/// as pairs of the key and the byte range of its value.
+pub(crate) fn dict_entries(data: &[u8]) -> Option<Vec<(&[u8], Range<usize>)>> {
+    if *data.first()? != b'd' {
+        return None;
+    }
+    let mut entries = Vec::new();
+    let mut pos = 1;
+    while *data.get(pos)? != b'e' {
+        let key = string_at(data, pos)?;
+        let end = value_end(data, key.end, 1)?;
+        entries.push((&data[key.clone()], key.end..end));
+        pos = end;
+    }
+    Some(entries)
+}
+
+/// How many bytes follow the value starting at `data[0]`, or `None` if it does not end.
+pub(crate) fn trailing_bytes(data: &[u8]) -> Option<usize> {
+    Some(data.len() - value_end(data, 0, 1)?)
+}
+
+/// Returns the raw bytes of the top-level `info` dictionary.
+pub(crate) fn info_slice(data: &[u8]) -> Option<&[u8]> {
+    let (_, range) = dict_entries(data)?
+        .into_iter()
+        .find(|(key, _)| *key == b"info")?;
+    Some(&data[range])
+}
+
+/// Returns the raw value of `key` in the dictionary starting at `data[0]`.
+pub(crate) fn dict_get<'a>(data: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let (_, range) = dict_entries(data)?.into_iter().find(|(k, _)| *k == key)?;
+    Some(&data[range])
+}
+
+/// Counts the elements of the list starting at `data[0]`, without decoding any of them.
+pub(crate) fn list_len(data: &[u8]) -> Option<usize> {
+    if *data.first()? != b'l' {
+        return None;
+    }
+    let mut len = 0;
+    let mut pos = 1;
+    while *data.get(pos)? != b'e' {
+        pos = value_end(data, pos, 1)?;
+        len += 1;
+    }
+    Some(len)
+}
+
+/// The content of `value` if it is exactly one byte string.
+pub(crate) fn as_bytes(value: &[u8]) -> Option<&[u8]> {
+    let range = string_at(value, 0)?;
+    (range.end == value.len()).then(|| &value[range])
+}
+
+/// `value` as an integer, if it is exactly one.
+pub(crate) fn as_int(value: &[u8]) -> Option<i64> {
+    std::str::from_utf8(value.strip_prefix(b"i")?.strip_suffix(b"e")?)
+        .ok()?
+        .parse()
+        .ok()
+}
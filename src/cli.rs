@@ -0,0 +1,53 @@
+//! Inspecting a single torrent from the command line, instead of running the server.
+
+use crate::config::Config;
+use crate::dates;
+use crate::decompress;
+use crate::inspection::{InspectOptions, Inspection};
+use crate::parse_torrent;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+/// Inspects the torrent at `path`, or read from stdin when `path` is `-`,
+/// and prints the inspection as JSON.
+pub(crate) fn run(path: &str, config: &Config) -> ExitCode {
+    match inspect(path, config) {
+        Ok(inspection) => {
+            // A closed pipe, like `| head`, is not worth a panic.
+            let _ = writeln!(io::stdout(), "{inspection}");
+            ExitCode::SUCCESS
+        }
+        Err(message) => {
+            eprintln!("torrent-inspector: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn inspect(path: &str, config: &Config) -> Result<String, String> {
+    let data_raw = if path == "-" {
+        let mut data = Vec::new();
+        io::stdin()
+            .read_to_end(&mut data)
+            .map_err(|e| format!("Failed to read stdin: {e}"))?;
+        if data.is_empty() {
+            return Err("No torrent on stdin, it was empty".to_string());
+        }
+        data
+    } else {
+        std::fs::read(path).map_err(|e| format!("Failed to read {path}: {e}"))?
+    };
+    let data_raw = if decompress::is_gzip(&data_raw) {
+        decompress::gunzip(&data_raw, config.max_decompressed_bytes).map_err(|e| e.to_string())?
+    } else {
+        data_raw
+    };
+
+    let torrent = parse_torrent(&data_raw, config).map_err(|e| e.message)?;
+    let options = InspectOptions {
+        now: (!config.deterministic).then(dates::now),
+        ..InspectOptions::default()
+    };
+    let inspection = Inspection::new(torrent, &data_raw, &options);
+    serde_json::to_string_pretty(&inspection).map_err(|e| e.to_string())
+}
@@ -0,0 +1,60 @@
+use serde::Serialize;
+
+/// The tool that created a torrent, as far as it can be told from **created by**.
+#[derive(Debug, Serialize)]
+pub(crate) struct Client {
+    pub client: &'static str,
+    pub version: Option<String>,
+}
+
+/// Known **created by** prefixes, matched case-insensitively, with the canonical client name.
+///
+/// Longer prefixes must come before shorter ones sharing the same start.
+const KNOWN_CLIENTS: &[(&str, &str)] = &[
+    ("qbittorrent", "qBittorrent"),
+    ("utorrent", "µTorrent"),
+    ("µtorrent", "µTorrent"),
+    ("bittorrent", "BitTorrent"),
+    ("bitcomet", "BitComet"),
+    ("transmission", "Transmission"),
+    ("deluge", "Deluge"),
+    ("mktorrent", "mktorrent"),
+    ("libtorrent", "libtorrent"),
+    ("rtorrent", "rTorrent"),
+    ("torrenttools", "torrenttools"),
+    ("py3createtorrent", "py3createtorrent"),
+    ("webtorrent", "WebTorrent"),
+    ("azureus", "Vuze"),
+    ("vuze", "Vuze"),
+    ("biglybt", "BiglyBT"),
+    ("ktorrent", "KTorrent"),
+    ("tixati", "Tixati"),
+    ("aria2", "aria2"),
+    ("intermodal", "intermodal"),
+    ("imdl", "intermodal"),
+];
+
+impl Client {
+    /// Classifies a **created by** string such as `qBittorrent v4.5.0` or `mktorrent 1.1`.
+    pub fn classify(created_by: &str) -> Option<Self> {
+        let lower = created_by.trim().to_lowercase();
+        let (prefix, client) = KNOWN_CLIENTS
+            .iter()
+            .find(|(prefix, _)| lower.starts_with(prefix))?;
+
+        let rest = lower[prefix.len()..].trim_start_matches([' ', '/', '-', '_']);
+        let rest = rest
+            .strip_prefix('v')
+            .filter(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+            .unwrap_or(rest);
+        let version: String = rest
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+            .collect();
+        let version = version
+            .starts_with(|c: char| c.is_ascii_digit())
+            .then_some(version);
+
+        Some(Client { client, version })
+    }
+}
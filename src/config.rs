@@ -0,0 +1,214 @@
+use std::env;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// Service configuration, read from the environment at startup.
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    /// Names of the multipart fields a torrent may be uploaded in, from `UPLOAD_FIELDS`
+    /// as a comma separated list. Defaults to just `file`.
+    pub upload_fields: Vec<String>,
+    /// Where uploaded torrents are saved, from `STORE_DIR`. Defaults to `/tmp`.
+    pub store_dir: PathBuf,
+    /// The largest request body accepted, from `MAX_UPLOAD_BYTES`. Defaults to 64 MiB.
+    pub max_upload_bytes: u64,
+    /// The most a gzip compressed upload may decompress to, from `MAX_DECOMPRESSED_BYTES`.
+    /// Defaults to 64 MiB.
+    pub max_decompressed_bytes: u64,
+    /// The most entries an uploaded archive may have, from `MAX_ARCHIVE_ENTRIES`. Defaults to 1000.
+    ///
+    /// The torrents extracted from it are limited to `max_decompressed_bytes` in total.
+    pub max_archive_entries: usize,
+    /// The most files a torrent may have, from `MAX_FILES`. Defaults to a million.
+    pub max_files: usize,
+    /// Whether torrents with more than `max_files` files have their v1 file list cut short with
+    /// a warning rather than being rejected, from `TOO_MANY_FILES=truncate`.
+    pub truncate_files: bool,
+    /// The most piece hashes listed by default, from `PIECE_DISPLAY_LIMIT`. Defaults to 1000,
+    /// and `?all=true` lists every one.
+    pub piece_display_limit: usize,
+    /// How many torrents of a batch, like an archive or the saved torrents, are parsed at once,
+    /// from `PARSE_WORKERS`. Defaults to the number of CPU cores.
+    pub parse_workers: usize,
+    /// How many bytes each client IP may upload per UTC day, from `UPLOAD_QUOTA_BYTES`.
+    /// Unlimited when unset.
+    pub upload_quota: Option<u64>,
+    /// Where upload quota usage is persisted, from `UPLOAD_QUOTA_FILE`. Kept in memory only when unset.
+    pub upload_quota_file: Option<PathBuf>,
+    /// How long responses to uploads with an `Idempotency-Key` are replayed for,
+    /// from `IDEMPOTENCY_TTL_SECS`. Defaults to a day.
+    pub idempotency_ttl: Duration,
+    /// The least time between two fetches from the same host by `/torrent/fetch`,
+    /// from `FETCH_SPACING_MS`. Defaults to a second.
+    pub fetch_spacing: Duration,
+    /// The longest `/torrent/fetch` waits for its turn to fetch from a host, from
+    /// `FETCH_MAX_WAIT_SECS`, before answering 503 instead. Defaults to 10 seconds.
+    pub fetch_max_wait: Duration,
+    /// Where `/torrent/:info_hash/verify` looks for the content of saved torrents, from
+    /// `CONTENT_DIR`, laid out like a client's download directory. Nothing can be verified
+    /// when unset.
+    pub content_dir: Option<PathBuf>,
+    /// The endpoint groups to serve, from `ENABLED_FEATURES` as a comma separated list
+    /// of names from [`FEATURES`]. Everything is served when unset.
+    pub enabled_features: Option<Vec<String>>,
+    /// The list of known good info-hashes `/torrent/known` checks against, from `KNOWN_HASHES_FILE`.
+    /// Nothing is known when unset.
+    pub known_hashes_file: Option<PathBuf>,
+    /// The bearer token of the endpoints that change what is saved, from `ADMIN_TOKEN`.
+    /// Nothing can be deleted when unset, and the rest of them are open.
+    pub admin_token: Option<String>,
+    /// The features whose endpoints require `admin_token`, from `AUTH_FEATURES` as a comma
+    /// separated list of names from [`FEATURES`]. Defaults to `put` and `delete`.
+    ///
+    /// `delete` always requires it, whether listed or not.
+    pub auth_features: Vec<String>,
+    /// The only tracker hostnames `/validate` accepts, from `ALLOWED_TRACKERS` as a comma
+    /// separated list. Any tracker is allowed when unset.
+    pub allowed_trackers: Option<Vec<String>>,
+    /// Whether responses must not depend on anything but the request, from `DETERMINISTIC`.
+    ///
+    /// Uploads are then not saved, `DELETE` is refused with 403, quotas never reset or persist,
+    /// replayed uploads never expire, there is no `Server-Timing` header, and request IDs count
+    /// up from 1 instead of being random. This is meant for tests running against the router.
+    pub deterministic: bool,
+    /// Whether nothing may be written to disk or fetched from the network, from `SAFE_MODE` or
+    /// the `--safe` flag.
+    ///
+    /// Uploads are then not saved and quota usage is kept in memory only. Requests that would
+    /// write, like `save_as` or any `PUT` or `DELETE`, and `/torrent/fetch` are refused with 403.
+    pub safe_mode: bool,
+}
+
+/// Names of the endpoint groups that can be turned on and off with `ENABLED_FEATURES`.
+pub(crate) const FEATURES: &[&str] = &[
+    "index",
+    "about",
+    "inspect",
+    "fetch",
+    "download",
+    "summary",
+    "files-csv",
+    "verify",
+    "trackers-txt",
+    "merge-trackers",
+    "overlap",
+    "magnet",
+    "match-magnet",
+    "reference",
+    "validate",
+    "resume",
+    "list",
+    "audit",
+    "stream",
+    "scan",
+    "archive",
+    "normalize",
+    "strip-trackers",
+    "pieces",
+    "piece-layers",
+    "layout",
+    "known-hashes",
+    "put",
+    "delete",
+    "ws",
+];
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            upload_fields: list("UPLOAD_FIELDS").unwrap_or_else(|| vec!["file".to_string()]),
+            store_dir: env::var_os("STORE_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from("/tmp")),
+            max_upload_bytes: env::var("MAX_UPLOAD_BYTES")
+                .ok()
+                .and_then(|max| max.parse().ok())
+                .unwrap_or(64 * 1024 * 1024),
+            max_decompressed_bytes: env::var("MAX_DECOMPRESSED_BYTES")
+                .ok()
+                .and_then(|max| max.parse().ok())
+                .unwrap_or(64 * 1024 * 1024),
+            max_archive_entries: env::var("MAX_ARCHIVE_ENTRIES")
+                .ok()
+                .and_then(|max| max.parse().ok())
+                .unwrap_or(1000),
+            max_files: env::var("MAX_FILES")
+                .ok()
+                .and_then(|max| max.parse().ok())
+                .unwrap_or(1_000_000),
+            truncate_files: env::var("TOO_MANY_FILES").is_ok_and(|value| value == "truncate"),
+            piece_display_limit: env::var("PIECE_DISPLAY_LIMIT")
+                .ok()
+                .and_then(|limit| limit.parse().ok())
+                .unwrap_or(1000),
+            parse_workers: env::var("PARSE_WORKERS")
+                .ok()
+                .and_then(|workers| workers.parse().ok())
+                .or_else(|| thread::available_parallelism().ok().map(usize::from))
+                .unwrap_or(1)
+                .max(1),
+            upload_quota: env::var("UPLOAD_QUOTA_BYTES")
+                .ok()
+                .and_then(|quota| quota.parse().ok()),
+            upload_quota_file: env::var_os("UPLOAD_QUOTA_FILE").map(PathBuf::from),
+            idempotency_ttl: Duration::from_secs(
+                env::var("IDEMPOTENCY_TTL_SECS")
+                    .ok()
+                    .and_then(|ttl| ttl.parse().ok())
+                    .unwrap_or(24 * 60 * 60),
+            ),
+            fetch_spacing: Duration::from_millis(
+                env::var("FETCH_SPACING_MS")
+                    .ok()
+                    .and_then(|spacing| spacing.parse().ok())
+                    .unwrap_or(1000),
+            ),
+            fetch_max_wait: Duration::from_secs(
+                env::var("FETCH_MAX_WAIT_SECS")
+                    .ok()
+                    .and_then(|wait| wait.parse().ok())
+                    .unwrap_or(10),
+            ),
+            content_dir: env::var_os("CONTENT_DIR").map(PathBuf::from),
+            enabled_features: list("ENABLED_FEATURES"),
+            known_hashes_file: env::var_os("KNOWN_HASHES_FILE").map(PathBuf::from),
+            admin_token: env::var("ADMIN_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            auth_features: list("AUTH_FEATURES")
+                .unwrap_or_else(|| vec!["put".to_string(), "delete".to_string()]),
+            allowed_trackers: list("ALLOWED_TRACKERS"),
+            deterministic: env::var("DETERMINISTIC")
+                .is_ok_and(|value| value == "1" || value == "true"),
+            safe_mode: env::var("SAFE_MODE").is_ok_and(|value| value == "1" || value == "true"),
+        }
+    }
+
+    /// Whether the endpoints of `feature` require the admin token.
+    pub fn requires_token(&self, feature: &str) -> bool {
+        feature == "delete"
+            || (self.admin_token.is_some() && self.auth_features.iter().any(|f| f == feature))
+    }
+
+    /// Whether the endpoints of `feature` should be served.
+    pub fn is_enabled(&self, feature: &str) -> bool {
+        match &self.enabled_features {
+            Some(features) => features.iter().any(|enabled| enabled == feature),
+            None => true,
+        }
+    }
+}
+
+/// Reads a comma separated list, ignoring empty items.
+fn list(key: &str) -> Option<Vec<String>> {
+    let value = env::var(key).ok()?;
+    Some(
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(String::from)
+            .collect(),
+    )
+}
@@ -0,0 +1,84 @@
+//! What matters when seeding the same content from torrents of several trackers.
+//!
+//! Trackers tend to re-create torrents for content they host, so copies of the same files come
+//! with different metadata. Cross-seeding tools match such torrents by their files instead.
+
+use crate::extra::ExtraValue;
+use crate::torrent::Torrent;
+use serde::Serialize;
+
+/// A field that commonly differs between the copies of a torrent on different trackers.
+#[derive(Debug, Serialize)]
+pub(crate) struct CrossSeedNote {
+    pub field: &'static str,
+    /// The value in this torrent, or `None` when the field is absent.
+    pub value: Option<String>,
+    /// Whether a different value keeps this torrent from matching another copy by info-hash.
+    ///
+    /// That is the case for every field of the info dictionary: the info-hash changes with it,
+    /// so the copies are separate swarms even when their files are the same.
+    pub prevents_match: bool,
+    pub note: &'static str,
+}
+
+/// Notes on each of the fields that usually set copies of the same content apart.
+pub(crate) fn notes(torrent: &Torrent) -> Vec<CrossSeedNote> {
+    let source = match torrent.info.extra.get("source") {
+        Some(ExtraValue::Bytes(source)) => Some(String::from_utf8_lossy(source).into_owned()),
+        _ => None,
+    };
+    vec![
+        CrossSeedNote {
+            field: "source",
+            value: source,
+            prevents_match: true,
+            note: "Set by trackers to give their copy its own info-hash; the files are unaffected",
+        },
+        CrossSeedNote {
+            field: "private",
+            value: torrent.info.private.map(|private| private.to_string()),
+            prevents_match: true,
+            note: "Only changes how peers are found; the files are unaffected",
+        },
+        CrossSeedNote {
+            field: "created by",
+            value: torrent.created_by.clone(),
+            prevents_match: false,
+            note: "Outside the info dictionary, so it never matters",
+        },
+        CrossSeedNote {
+            field: "piece length",
+            value: Some(torrent.info.piece_length.to_string()),
+            prevents_match: true,
+            note: "Complete files still match, but pieces are cut differently, \
+                   so partially downloaded data cannot be checked against the other copy",
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_info_fields_prevent_a_match() {
+        let torrent: Torrent = serde_bencode::from_bytes(
+            b"d10:created by4:test4:infod6:lengthi1e4:name1:x12:piece lengthi16384e\
+              6:pieces0:7:privatei1e6:source3:ABCee",
+        )
+        .unwrap();
+        let notes: Vec<_> = notes(&torrent)
+            .into_iter()
+            .map(|note| (note.field, note.value, note.prevents_match))
+            .collect();
+        assert_eq!(
+            notes,
+            [
+                ("source", Some("ABC".to_string()), true),
+                ("private", Some("1".to_string()), true),
+                ("created by", Some("test".to_string()), false),
+                ("piece length", Some("16384".to_string()), true),
+            ]
+        );
+    }
+}
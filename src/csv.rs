@@ -0,0 +1,96 @@
+//! The file list as CSV, for spreadsheets.
+
+use crate::torrent::Info;
+use std::fmt::Write;
+
+/// Renders one row per file, with the range of pieces it spans.
+///
+/// v1 content is one long stream cut into pieces, so files may share their first and last pieces.
+/// In pure v2 torrents every file starts on a piece boundary of its own.
+/// Empty files span no pieces at all, and leave both columns blank. So do the files of a crafted
+/// torrent from where their offsets no longer fit in a `u64`.
+pub(crate) fn files_csv(info: &Info) -> String {
+    let piece_length = info.piece_length.max(1) as u64;
+    let aligned = !info.has_v1();
+
+    let mut csv = String::from("path,length,md5sum,piece_start,piece_end\r\n");
+    let mut offset = Some(0u64);
+    for file in info.file_entries() {
+        if aligned {
+            offset = offset.and_then(|offset| offset.checked_next_multiple_of(piece_length));
+        }
+        let span: Option<(u64, u64)> = try {
+            let start = offset?;
+            let end = start.checked_add(file.length.checked_sub(1)?)?;
+            (start / piece_length, end / piece_length)
+        };
+        let pieces = match span {
+            Some((start, end)) => format!("{start},{end}"),
+            None => String::from(","),
+        };
+        let _ = write!(
+            csv,
+            "{},{},{},{pieces}\r\n",
+            escape(&file.path.join("/")),
+            file.length,
+            escape(file.md5sum.unwrap_or_default()),
+        );
+        offset = offset.and_then(|offset| offset.checked_add(file.length));
+    }
+    csv
+}
+
+/// Quotes a field per RFC 4180 when it contains a separator, quote or line break.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(data: &str) -> Info {
+        serde_bencode::from_str::<crate::torrent::Torrent>(data)
+            .unwrap()
+            .info
+    }
+
+    #[test]
+    fn spans_pieces_and_escapes_paths() {
+        let info = info(
+            "d4:infod5:filesld6:lengthi10e4:pathl5:a,b.ceed6:lengthi0e4:pathl1:eee\
+             d6:lengthi7e4:pathl2:q\"e6:md5sum32:0123456789abcdef0123456789abcdefee\
+             4:name1:x12:piece lengthi8e6:pieces0:ee",
+        );
+        assert_eq!(
+            files_csv(&info),
+            "path,length,md5sum,piece_start,piece_end\r\n\
+             \"a,b.c\",10,,0,1\r\n\
+             e,0,,,\r\n\
+             \"q\"\"\",7,0123456789abcdef0123456789abcdef,1,2\r\n"
+        );
+    }
+
+    #[test]
+    fn leaves_pieces_blank_once_offsets_overflow() {
+        let max = i64::MAX;
+        let info = info(&format!(
+            "d4:infod5:filesld6:lengthi{max}e4:pathl1:aeed6:lengthi{max}e4:pathl1:bee\
+             d6:lengthi{max}e4:pathl1:cee\
+             d6:lengthi1e4:pathl1:deee4:name1:x12:piece lengthi16384e6:pieces0:ee"
+        ));
+        let csv = files_csv(&info);
+        let rows: Vec<&str> = csv.lines().skip(1).collect();
+        assert_eq!(rows[0], format!("a,{max},,0,562949953421311"));
+        assert_eq!(
+            rows[1],
+            format!("b,{max},,562949953421311,1125899906842623")
+        );
+        assert_eq!(rows[2], format!("c,{max},,,"));
+        assert_eq!(rows[3], "d,1,,,");
+    }
+}
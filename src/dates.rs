@@ -0,0 +1,55 @@
+//! Describing timestamps and durations for display.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+const MONTH: u64 = 30 * DAY;
+const YEAR: u64 = 365 * DAY;
+
+/// Seconds since the Unix epoch.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Describes `timestamp` relative to `now` in its largest whole unit, like `3 years ago`
+/// or `in 2 days` for the future. Less than a minute either way is `just now`.
+pub(crate) fn relative(timestamp: u64, now: u64) -> String {
+    let distance = timestamp.abs_diff(now);
+    let (count, unit) = match distance {
+        d if d < MINUTE => return "just now".to_string(),
+        d if d < HOUR => (d / MINUTE, "minute"),
+        d if d < DAY => (d / HOUR, "hour"),
+        d if d < MONTH => (d / DAY, "day"),
+        d if d < YEAR => (d / MONTH, "month"),
+        d => (d / YEAR, "year"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    if timestamp > now {
+        format!("in {count} {unit}{plural}")
+    } else {
+        format!("{count} {unit}{plural} ago")
+    }
+}
+
+/// Describes a duration like `1d 2h 3m 4s`, leaving out units that are zero.
+pub(crate) fn human_duration(seconds: u64) -> String {
+    if seconds == 0 {
+        return "0s".to_string();
+    }
+    [(DAY, "d"), (HOUR, "h"), (MINUTE, "m"), (1, "s")]
+        .into_iter()
+        .scan(seconds, |left, (unit, suffix)| {
+            let count = *left / unit;
+            *left %= unit;
+            Some((count, suffix))
+        })
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, suffix)| format!("{count}{suffix}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
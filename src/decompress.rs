@@ -0,0 +1,86 @@
+use flate2::read::GzDecoder;
+use std::fmt;
+use std::io::Read;
+
+/// Why a compressed upload could not be decompressed.
+#[derive(Debug)]
+pub(crate) enum DecompressionError {
+    /// The upload decompresses to more than the configured limit, as a zip bomb would.
+    DecompressionLimitExceeded {
+        limit: u64,
+    },
+    Corrupt,
+}
+
+impl fmt::Display for DecompressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecompressionError::DecompressionLimitExceeded { limit } => {
+                write!(f, "Upload decompresses to more than {limit} bytes")
+            }
+            DecompressionError::Corrupt => write!(f, "Failed to decompress upload"),
+        }
+    }
+}
+
+/// Whether the data starts with the gzip magic bytes.
+pub(crate) fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&[0x1f, 0x8b])
+}
+
+/// Decompresses gzip data, giving up as soon as the output grows past `limit` bytes
+/// rather than inflating everything and checking the size afterwards.
+pub(crate) fn gunzip(data: &[u8], limit: u64) -> Result<Vec<u8>, DecompressionError> {
+    let mut decompressed = Vec::new();
+    GzDecoder::new(data)
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut decompressed)
+        .map_err(|_| DecompressionError::Corrupt)?;
+    if decompressed.len() as u64 > limit {
+        return Err(DecompressionError::DecompressionLimitExceeded { limit });
+    }
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn stops_at_the_limit() {
+        // A few KiB that inflate to 16 MiB.
+        let bomb = gzip(&vec![0; 16 << 20]);
+        assert!(bomb.len() < 64 << 10);
+        assert!(is_gzip(&bomb));
+        assert!(matches!(
+            gunzip(&bomb, 1 << 20),
+            Err(DecompressionError::DecompressionLimitExceeded { limit }) if limit == 1 << 20
+        ));
+    }
+
+    #[test]
+    fn allows_up_to_the_limit() {
+        let data = gzip(b"d4:infodee");
+        assert_eq!(gunzip(&data, 10).unwrap(), b"d4:infodee");
+        assert!(gunzip(&data, 9).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_data() {
+        let mut data = gzip(b"d4:infodee");
+        data.truncate(data.len() - 10);
+        assert!(matches!(
+            gunzip(&data, 1 << 20),
+            Err(DecompressionError::Corrupt)
+        ));
+    }
+}
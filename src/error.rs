@@ -0,0 +1,530 @@
+//! Machine readable reasons for failed requests, and the one way they are answered.
+
+use crate::archive::ArchiveError;
+use crate::decompress::DecompressionError;
+use crate::fetch::FetchError;
+use crate::format::Format;
+use axum::body::Body;
+use axum::http::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE};
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+/// What went wrong, as sent in the `code` field of a failed response.
+///
+/// The codes are part of the API: a code is never renamed or reused for something else,
+/// even when the human readable message next to it changes. New codes may be added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ErrorCode {
+    /// The multipart body could not be read, for example because it was cut off.
+    UploadUnreadable,
+    /// None of the upload fields is named as configured.
+    NoTorrentUploaded,
+    /// The request body, or a fetched torrent, is larger than `MAX_UPLOAD_BYTES`.
+    UploadTooLarge,
+    /// The client IP used up its daily upload quota.
+    QuotaExceeded,
+    /// A gzip upload decompresses to more than `MAX_DECOMPRESSED_BYTES`.
+    DecompressionLimitExceeded,
+    /// A gzip upload is corrupt.
+    CorruptCompression,
+    /// The upload is not a bencoded dictionary.
+    NotBencode,
+    /// The upload ends in the middle of a bencoded value.
+    Truncated,
+    /// The upload is bencoded, but not a valid metainfo file.
+    InvalidMetainfo,
+    /// The torrent has more than `MAX_FILES` files.
+    TooManyFiles,
+    /// The upload is not valid libtorrent resume data.
+    InvalidResumeData,
+    /// An uploaded archive is neither zip nor tar.
+    UnsupportedArchive,
+    /// An uploaded archive has more than `MAX_ARCHIVE_ENTRIES` entries.
+    TooManyArchiveEntries,
+    /// The torrents in an uploaded archive add up to more than `MAX_DECOMPRESSED_BYTES`.
+    ArchiveLimitExceeded,
+    /// An uploaded archive is corrupt.
+    CorruptArchive,
+    /// A parameter or an extra field of the request is missing or invalid.
+    InvalidRequest,
+    /// Torrents that were expected to be the same are not.
+    TorrentMismatch,
+    /// Normalizing the torrent would have changed its info-hash.
+    NormalizationFailed,
+    /// No saved torrent has the requested info-hash.
+    NotFound,
+    /// An info-hash prefix matches more than one saved torrent.
+    AmbiguousPrefix,
+    /// The saved torrents could not be read.
+    StorageUnavailable,
+    /// The request lacks the bearer token the endpoint requires.
+    Unauthorized,
+    /// The request would write to disk, which `SAFE_MODE` does not allow.
+    SafeMode,
+    /// The request would delete a saved torrent, which `DETERMINISTIC` does not allow.
+    ReadOnly,
+    /// An `Idempotency-Key` was sent again with a different request body.
+    IdempotencyKeyReused,
+    /// The first request with an `Idempotency-Key` has not been answered yet.
+    IdempotencyKeyInFlight,
+    /// The host of a URL to fetch asked not to be fetched from for now, or is already
+    /// fetched from too often. `Retry-After` tells when to try again.
+    FetchThrottled,
+    /// A URL could not be fetched, or did not answer with a torrent.
+    FetchFailed,
+    /// There is no content to verify against, as `CONTENT_DIR` is not set.
+    ContentUnavailable,
+}
+
+impl ErrorCode {
+    /// The HTTP status a failure with this code is answered with.
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::UploadUnreadable
+            | ErrorCode::NoTorrentUploaded
+            | ErrorCode::CorruptCompression
+            | ErrorCode::CorruptArchive
+            | ErrorCode::InvalidRequest
+            | ErrorCode::TorrentMismatch => StatusCode::BAD_REQUEST,
+            ErrorCode::NotBencode
+            | ErrorCode::Truncated
+            | ErrorCode::InvalidMetainfo
+            | ErrorCode::TooManyFiles
+            | ErrorCode::InvalidResumeData
+            | ErrorCode::NormalizationFailed
+            | ErrorCode::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::UploadTooLarge
+            | ErrorCode::DecompressionLimitExceeded
+            | ErrorCode::TooManyArchiveEntries
+            | ErrorCode::ArchiveLimitExceeded => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::UnsupportedArchive => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::QuotaExceeded => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::AmbiguousPrefix | ErrorCode::IdempotencyKeyInFlight => StatusCode::CONFLICT,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::SafeMode | ErrorCode::ReadOnly => StatusCode::FORBIDDEN,
+            ErrorCode::StorageUnavailable
+            | ErrorCode::FetchThrottled
+            | ErrorCode::ContentUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::FetchFailed => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+/// A failed request: the code to branch on, a message to show, and optionally details to act on.
+///
+/// Handlers return it as the error of their `Result`. As a response, its status comes from
+/// [`ErrorCode::status`] and its body is an [`ErrorResponse`].
+#[derive(Debug, Clone)]
+pub(crate) struct AppError {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Whatever the code is about, such as the limit that was exceeded.
+    pub details: Option<serde_json::Value>,
+}
+
+impl AppError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        AppError {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: impl Serialize) -> Self {
+        self.details = serde_json::to_value(details).ok();
+        self
+    }
+
+    /// Tells apart why bencoded data could not be deserialized.
+    pub fn parse(data: &[u8], error: serde_bencode::Error) -> Self {
+        match error {
+            _ if !data.starts_with(b"d") => {
+                AppError::new(ErrorCode::NotBencode, "Upload is not a bencoded dictionary")
+            }
+            serde_bencode::Error::EndOfStream => {
+                AppError::new(ErrorCode::Truncated, "Upload is truncated")
+            }
+            error => AppError::new(
+                ErrorCode::InvalidMetainfo,
+                format!("Failed to parse torrent: {error}"),
+            ),
+        }
+    }
+}
+
+impl From<DecompressionError> for AppError {
+    fn from(error: DecompressionError) -> Self {
+        let (code, limit) = match error {
+            DecompressionError::DecompressionLimitExceeded { limit } => {
+                (ErrorCode::DecompressionLimitExceeded, Some(limit))
+            }
+            DecompressionError::Corrupt => (ErrorCode::CorruptCompression, None),
+        };
+        let error = AppError::new(code, error.to_string());
+        match limit {
+            Some(limit) => error.with_details(serde_json::json!({ "limit": limit })),
+            None => error,
+        }
+    }
+}
+
+impl From<ArchiveError> for AppError {
+    fn from(error: ArchiveError) -> Self {
+        let (code, limit) = match error {
+            ArchiveError::UnsupportedFormat => (ErrorCode::UnsupportedArchive, None),
+            ArchiveError::TooManyEntries { limit } => {
+                (ErrorCode::TooManyArchiveEntries, Some(limit as u64))
+            }
+            ArchiveError::SizeLimitExceeded { limit } => {
+                (ErrorCode::ArchiveLimitExceeded, Some(limit))
+            }
+            ArchiveError::Corrupt => (ErrorCode::CorruptArchive, None),
+        };
+        let error = AppError::new(code, error.to_string());
+        match limit {
+            Some(limit) => error.with_details(serde_json::json!({ "limit": limit })),
+            None => error,
+        }
+    }
+}
+
+impl From<FetchError> for AppError {
+    fn from(error: FetchError) -> Self {
+        let message = error.to_string();
+        match error {
+            FetchError::InvalidUrl { .. } | FetchError::NotPublic { .. } => {
+                AppError::new(ErrorCode::InvalidRequest, message)
+            }
+            FetchError::Throttled { host, retry_after } => {
+                AppError::new(ErrorCode::FetchThrottled, message).with_details(serde_json::json!({
+                    "host": host,
+                    "retry_after": retry_after.as_secs(),
+                }))
+            }
+            FetchError::TooLarge { limit } => AppError::new(ErrorCode::UploadTooLarge, message)
+                .with_details(serde_json::json!({ "limit": limit })),
+            FetchError::Failed { .. } => AppError::new(ErrorCode::FetchFailed, message),
+        }
+    }
+}
+
+/// The body of every failed response, whichever endpoint it comes from:
+/// `{"type": "fail", "code": …, "message": …, "details": …, "data": …}`.
+///
+/// `details` is left out when there are none. `data` repeats `message`, for clients written
+/// against the envelope from before there was a `message`.
+pub(crate) struct ErrorResponse<'a>(pub &'a AppError);
+
+impl Serialize for ErrorResponse<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let error = self.0;
+        // Binary formats write this length ahead of the fields, so it must leave out `details`
+        // whenever that is skipped.
+        let fields = if error.details.is_some() { 5 } else { 4 };
+        let mut response = serializer.serialize_struct("ErrorResponse", fields)?;
+        response.serialize_field("type", "fail")?;
+        response.serialize_field("code", &error.code)?;
+        response.serialize_field("message", &error.message)?;
+        match &error.details {
+            Some(details) => response.serialize_field("details", details)?,
+            None => response.skip_field("details")?,
+        }
+        response.serialize_field("data", &error.message)?;
+        response.end()
+    }
+}
+
+/// Answers in JSON. [`negotiate`] re-encodes the body when the client asked for another format,
+/// which the error itself cannot know about.
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let mut response = (self.code.status(), Json(ErrorResponse(&self))).into_response();
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+/// Encodes failed responses in the format the `Accept` header asks for, like successful ones.
+///
+/// Headers set next to the error, like `WWW-Authenticate`, are kept.
+pub(crate) async fn negotiate(request: Request<Body>, next: Next<Body>) -> Response {
+    let accept = request
+        .headers()
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .unwrap_or_default();
+    let format = Format::from_accept(accept);
+    let mut response = next.run(request).await;
+    if format == Format::Json {
+        return response;
+    }
+    let Some(error) = response.extensions_mut().remove::<AppError>() else {
+        return response;
+    };
+    let mut encoded = format.respond_with(response.status(), ErrorResponse(&error));
+    for (name, value) in response.headers() {
+        if name != CONTENT_TYPE && name != CONTENT_LENGTH {
+            encoded.headers_mut().append(name, value.clone());
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::HttpBody;
+    use axum::routing::get;
+    use axum::{middleware, Router};
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    /// Every code, with how it is sent and the status it is answered with.
+    ///
+    /// Codes are part of the API, so these are spelled out rather than derived.
+    const CODES: &[(ErrorCode, &str, StatusCode)] = &[
+        (
+            ErrorCode::UploadUnreadable,
+            "upload_unreadable",
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            ErrorCode::NoTorrentUploaded,
+            "no_torrent_uploaded",
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            ErrorCode::UploadTooLarge,
+            "upload_too_large",
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ),
+        (
+            ErrorCode::QuotaExceeded,
+            "quota_exceeded",
+            StatusCode::TOO_MANY_REQUESTS,
+        ),
+        (
+            ErrorCode::DecompressionLimitExceeded,
+            "decompression_limit_exceeded",
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ),
+        (
+            ErrorCode::CorruptCompression,
+            "corrupt_compression",
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            ErrorCode::NotBencode,
+            "not_bencode",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        (
+            ErrorCode::Truncated,
+            "truncated",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        (
+            ErrorCode::InvalidMetainfo,
+            "invalid_metainfo",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        (
+            ErrorCode::TooManyFiles,
+            "too_many_files",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        (
+            ErrorCode::InvalidResumeData,
+            "invalid_resume_data",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        (
+            ErrorCode::UnsupportedArchive,
+            "unsupported_archive",
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        ),
+        (
+            ErrorCode::TooManyArchiveEntries,
+            "too_many_archive_entries",
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ),
+        (
+            ErrorCode::ArchiveLimitExceeded,
+            "archive_limit_exceeded",
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ),
+        (
+            ErrorCode::CorruptArchive,
+            "corrupt_archive",
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            ErrorCode::InvalidRequest,
+            "invalid_request",
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            ErrorCode::TorrentMismatch,
+            "torrent_mismatch",
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            ErrorCode::NormalizationFailed,
+            "normalization_failed",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        (ErrorCode::NotFound, "not_found", StatusCode::NOT_FOUND),
+        (
+            ErrorCode::AmbiguousPrefix,
+            "ambiguous_prefix",
+            StatusCode::CONFLICT,
+        ),
+        (
+            ErrorCode::StorageUnavailable,
+            "storage_unavailable",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        (
+            ErrorCode::Unauthorized,
+            "unauthorized",
+            StatusCode::UNAUTHORIZED,
+        ),
+        (ErrorCode::SafeMode, "safe_mode", StatusCode::FORBIDDEN),
+        (ErrorCode::ReadOnly, "read_only", StatusCode::FORBIDDEN),
+        (
+            ErrorCode::IdempotencyKeyReused,
+            "idempotency_key_reused",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        (
+            ErrorCode::IdempotencyKeyInFlight,
+            "idempotency_key_in_flight",
+            StatusCode::CONFLICT,
+        ),
+        (
+            ErrorCode::FetchThrottled,
+            "fetch_throttled",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        (
+            ErrorCode::FetchFailed,
+            "fetch_failed",
+            StatusCode::BAD_GATEWAY,
+        ),
+        (
+            ErrorCode::ContentUnavailable,
+            "content_unavailable",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+    ];
+
+    /// Fails to compile when a code is added, as a reminder to list it in [`CODES`].
+    #[allow(dead_code)]
+    fn listed(code: ErrorCode) {
+        match code {
+            ErrorCode::UploadUnreadable
+            | ErrorCode::NoTorrentUploaded
+            | ErrorCode::UploadTooLarge
+            | ErrorCode::QuotaExceeded
+            | ErrorCode::DecompressionLimitExceeded
+            | ErrorCode::CorruptCompression
+            | ErrorCode::NotBencode
+            | ErrorCode::Truncated
+            | ErrorCode::InvalidMetainfo
+            | ErrorCode::TooManyFiles
+            | ErrorCode::InvalidResumeData
+            | ErrorCode::UnsupportedArchive
+            | ErrorCode::TooManyArchiveEntries
+            | ErrorCode::ArchiveLimitExceeded
+            | ErrorCode::CorruptArchive
+            | ErrorCode::InvalidRequest
+            | ErrorCode::TorrentMismatch
+            | ErrorCode::NormalizationFailed
+            | ErrorCode::NotFound
+            | ErrorCode::AmbiguousPrefix
+            | ErrorCode::StorageUnavailable
+            | ErrorCode::Unauthorized
+            | ErrorCode::SafeMode
+            | ErrorCode::ReadOnly
+            | ErrorCode::IdempotencyKeyReused
+            | ErrorCode::IdempotencyKeyInFlight
+            | ErrorCode::FetchThrottled
+            | ErrorCode::FetchFailed
+            | ErrorCode::ContentUnavailable => {}
+        }
+    }
+
+    #[test]
+    fn every_code_has_its_status_and_envelope() {
+        for &(code, name, status) in CODES {
+            assert_eq!(code.status(), status, "{name}");
+
+            let error = AppError::new(code, "went wrong");
+            assert_eq!(
+                serde_json::to_value(ErrorResponse(&error)).unwrap(),
+                json!({ "type": "fail", "code": name, "message": "went wrong", "data": "went wrong" }),
+            );
+            let error = error.with_details(json!({ "limit": 1 }));
+            assert_eq!(
+                serde_json::to_value(ErrorResponse(&error)).unwrap(),
+                json!({
+                    "type": "fail",
+                    "code": name,
+                    "message": "went wrong",
+                    "details": { "limit": 1 },
+                    "data": "went wrong",
+                }),
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn errors_are_encoded_as_accepted() {
+        let router = Router::new()
+            .route(
+                "/",
+                get(|| async {
+                    (
+                        [("www-authenticate", "Bearer")],
+                        AppError::new(ErrorCode::Unauthorized, "no token"),
+                    )
+                }),
+            )
+            .layer(middleware::from_fn(negotiate));
+        let expected = json!({
+            "type": "fail",
+            "code": "unauthorized",
+            "message": "no token",
+            "data": "no token",
+        });
+
+        for accept in ["application/msgpack", "application/cbor"] {
+            let request = Request::get("/")
+                .header(ACCEPT, accept)
+                .body(Body::empty())
+                .unwrap();
+            let response = router.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(response.headers()[CONTENT_TYPE], accept);
+            assert_eq!(response.headers()["www-authenticate"], "Bearer");
+
+            let mut body = response.into_body();
+            let mut bytes = Vec::new();
+            while let Some(chunk) = body.data().await {
+                bytes.extend(chunk.unwrap());
+            }
+            let decoded: serde_json::Value = match accept {
+                "application/msgpack" => rmp_serde::from_slice(&bytes).unwrap(),
+                _ => serde_cbor::from_slice(&bytes).unwrap(),
+            };
+            assert_eq!(decoded, expected, "{accept}");
+        }
+    }
+}
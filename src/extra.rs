@@ -0,0 +1,101 @@
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Keys of a dictionary that the typed model does not know about, kept in sorted order
+/// so that they always serialize the same way.
+pub(crate) type Extra = BTreeMap<String, ExtraValue>;
+
+/// Any bencoded value.
+///
+/// Unlike `serde_bencode::value::Value`, dictionaries keep their keys sorted, and byte strings
+/// that are valid UTF-8 serialize as strings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ExtraValue {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<ExtraValue>),
+    Dict(BTreeMap<Vec<u8>, ExtraValue>),
+}
+
+impl Serialize for ExtraValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ExtraValue::Int(value) => serializer.serialize_i64(*value),
+            ExtraValue::Bytes(bytes) => match std::str::from_utf8(bytes) {
+                Ok(string) => serializer.serialize_str(string),
+                Err(_) => serializer.serialize_bytes(bytes),
+            },
+            ExtraValue::List(values) => {
+                let mut seq = serializer.serialize_seq(Some(values.len()))?;
+                for value in values {
+                    seq.serialize_element(value)?;
+                }
+                seq.end()
+            }
+            ExtraValue::Dict(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(&String::from_utf8_lossy(key), value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ExtraValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ExtraVisitor;
+
+        impl<'de> Visitor<'de> for ExtraVisitor {
+            type Value = ExtraValue;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("any bencoded value")
+            }
+
+            fn visit_i64<E>(self, value: i64) -> Result<ExtraValue, E> {
+                Ok(ExtraValue::Int(value))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<ExtraValue, E> {
+                i64::try_from(value)
+                    .map(ExtraValue::Int)
+                    .map_err(|_| E::custom("integer out of range"))
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<ExtraValue, E> {
+                Ok(ExtraValue::Bytes(value.as_bytes().to_vec()))
+            }
+
+            fn visit_bytes<E>(self, value: &[u8]) -> Result<ExtraValue, E> {
+                Ok(ExtraValue::Bytes(value.to_vec()))
+            }
+
+            fn visit_byte_buf<E>(self, value: Vec<u8>) -> Result<ExtraValue, E> {
+                Ok(ExtraValue::Bytes(value))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ExtraValue, A::Error> {
+                let mut values = Vec::new();
+                while let Some(value) = seq.next_element()? {
+                    values.push(value);
+                }
+                Ok(ExtraValue::List(values))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<ExtraValue, A::Error> {
+                let mut entries = BTreeMap::new();
+                while let Some((key, value)) = map.next_entry::<serde_bytes::ByteBuf, _>()? {
+                    entries.insert(key.into_vec(), value);
+                }
+                Ok(ExtraValue::Dict(entries))
+            }
+        }
+
+        deserializer.deserialize_any(ExtraVisitor)
+    }
+}
@@ -0,0 +1,367 @@
+//! Downloading torrents from a URL, without hammering the hosts they come from.
+//!
+//! Only public addresses are fetched from, on every hop, so that the endpoint cannot be used to
+//! reach into the network the server is in.
+
+use crate::config::Config;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::header::{LOCATION, RETRY_AFTER};
+use reqwest::redirect::Policy;
+use reqwest::{ClientBuilder, StatusCode};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use url::{Host, Url};
+
+/// How long a single fetch may take, redirects included.
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// The most redirects followed before giving up.
+const MAX_REDIRECTS: usize = 5;
+
+/// Query parameters of `/torrent/fetch`.
+#[derive(Debug, Deserialize)]
+pub(crate) struct FetchOptions {
+    /// The torrent to fetch, an `http` or `https` URL.
+    pub url: String,
+}
+
+#[derive(Debug)]
+pub(crate) enum FetchError {
+    /// The URL is not an absolute `http` or `https` URL with a host.
+    InvalidUrl { url: String },
+    /// The host is, or only resolves to, an address that is not public, like a loopback or
+    /// private one.
+    NotPublic { host: String },
+    /// The host cannot be fetched from for `retry_after`, either because it asked for that with
+    /// `Retry-After` or because too many fetches from it are already lined up.
+    Throttled { host: String, retry_after: Duration },
+    /// The torrent is larger than `limit` bytes.
+    TooLarge { limit: u64 },
+    /// The host could not be reached, or answered with something other than the torrent.
+    Failed { url: String, reason: String },
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::InvalidUrl { url } => write!(f, "{url} is not an http or https URL"),
+            FetchError::NotPublic { host } => write!(f, "{host} is not a public address"),
+            FetchError::Throttled { host, retry_after } => write!(
+                f,
+                "Fetching from {host} again is not allowed for another {}s",
+                retry_after.as_secs()
+            ),
+            FetchError::TooLarge { limit } => {
+                write!(f, "The fetched torrent is larger than {limit} bytes")
+            }
+            FetchError::Failed { url, reason } => write!(f, "Failed to fetch {url}: {reason}"),
+        }
+    }
+}
+
+/// Fetches torrents over HTTP, spacing out the requests to each host.
+#[derive(Debug)]
+pub(crate) struct Fetcher {
+    client: reqwest::Client,
+    throttle: Throttle,
+}
+
+impl Fetcher {
+    pub fn new(config: &Config) -> Self {
+        Fetcher::with_client(config, reqwest::Client::builder())
+    }
+
+    fn with_client(config: &Config, client: ClientBuilder) -> Self {
+        let client = client
+            .user_agent(concat!("torrent-inspector/", env!("CARGO_PKG_VERSION")))
+            .timeout(TIMEOUT)
+            // Redirects are followed by hand, so that every host on the way is throttled.
+            .redirect(Policy::none())
+            .dns_resolver(Arc::new(PublicOnly))
+            .build()
+            .expect("the HTTP client has a valid configuration");
+        Fetcher {
+            client,
+            throttle: Throttle::new(config.fetch_spacing, config.fetch_max_wait),
+        }
+    }
+
+    /// Downloads `url`, up to `limit` bytes.
+    ///
+    /// Waits for its turn if the host was fetched from shortly before, but fails with
+    /// [`FetchError::Throttled`] instead of waiting longer than `FETCH_MAX_WAIT_SECS`.
+    pub async fn fetch(&self, url: &str, limit: u64) -> Result<Vec<u8>, FetchError> {
+        let invalid = || FetchError::InvalidUrl {
+            url: url.to_string(),
+        };
+        let mut url = Url::parse(url).map_err(|_| invalid())?;
+        for _ in 0..=MAX_REDIRECTS {
+            let host = match url.scheme() {
+                "http" | "https" => url.host_str().ok_or_else(invalid)?.to_string(),
+                _ => return Err(invalid()),
+            };
+            // Names are checked as they are resolved, by `PublicOnly`.
+            let ip = match url.host() {
+                Some(Host::Ipv4(ip)) => Some(IpAddr::V4(ip)),
+                Some(Host::Ipv6(ip)) => Some(IpAddr::V6(ip)),
+                _ => None,
+            };
+            if ip.is_some_and(|ip| !ip.is_global()) {
+                return Err(FetchError::NotPublic { host });
+            }
+            let failed = |reason: String| FetchError::Failed {
+                url: url.to_string(),
+                reason,
+            };
+
+            let at = self
+                .throttle
+                .reserve(&host, Instant::now())
+                .map_err(|retry_after| FetchError::Throttled {
+                    host: host.clone(),
+                    retry_after,
+                })?;
+            tokio::time::sleep_until(at.into()).await;
+            let mut response = self.client.get(url.clone()).send().await.map_err(|e| {
+                match NotPublic::caused(&e) {
+                    true => FetchError::NotPublic { host: host.clone() },
+                    false => failed(e.to_string()),
+                }
+            })?;
+
+            let status = response.status();
+            if status.is_redirection() {
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .and_then(|location| url.join(location).ok())
+                    .ok_or_else(|| failed(format!("{status} without a valid Location")))?;
+                url = location;
+                continue;
+            }
+            if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE
+            {
+                if let Some(retry_after) = response
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| retry_after(value, SystemTime::now()))
+                {
+                    self.throttle.back_off(&host, Instant::now() + retry_after);
+                    return Err(FetchError::Throttled { host, retry_after });
+                }
+            }
+            if !status.is_success() {
+                return Err(failed(status.to_string()));
+            }
+
+            if response
+                .content_length()
+                .is_some_and(|length| length > limit)
+            {
+                return Err(FetchError::TooLarge { limit });
+            }
+            let mut data = Vec::new();
+            while let Some(chunk) = response.chunk().await.map_err(|e| failed(e.to_string()))? {
+                if data.len() as u64 + chunk.len() as u64 > limit {
+                    return Err(FetchError::TooLarge { limit });
+                }
+                data.extend_from_slice(&chunk);
+            }
+            return Ok(data);
+        }
+        Err(FetchError::Failed {
+            url: url.to_string(),
+            reason: format!("more than {MAX_REDIRECTS} redirects"),
+        })
+    }
+}
+
+/// Resolves host names to their public addresses only, leaving out any others.
+///
+/// Checking the addresses as they are connected to, rather than resolving the name beforehand,
+/// keeps a name from resolving to a public address for the check and a private one after.
+struct PublicOnly;
+
+impl Resolve for PublicOnly {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| addr.ip().is_global())
+                .collect();
+            if addrs.is_empty() {
+                return Err(NotPublic.into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// The error of [`PublicOnly`] for a name without any public address.
+#[derive(Debug)]
+struct NotPublic;
+
+impl NotPublic {
+    /// Whether `error` was caused by a `NotPublic`.
+    fn caused(error: &(dyn Error + 'static)) -> bool {
+        let mut cause = Some(error);
+        while let Some(error) = cause {
+            if error.is::<NotPublic>() {
+                return true;
+            }
+            cause = error.source();
+        }
+        false
+    }
+}
+
+impl fmt::Display for NotPublic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("no public address")
+    }
+}
+
+impl Error for NotPublic {}
+
+/// Reads a `Retry-After` header, either a number of seconds or an HTTP date.
+///
+/// A date in the past means there is nothing to wait for.
+fn retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value.trim()).ok()?;
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+/// When each host may be fetched from next.
+#[derive(Debug)]
+struct Throttle {
+    /// The least time between two fetches from the same host, from `FETCH_SPACING_MS`.
+    spacing: Duration,
+    /// The longest a fetch waits for its turn, from `FETCH_MAX_WAIT_SECS`.
+    max_wait: Duration,
+    /// Only hosts that cannot be fetched from right away are kept.
+    hosts: Mutex<HashMap<String, Instant>>,
+}
+
+impl Throttle {
+    fn new(spacing: Duration, max_wait: Duration) -> Self {
+        Throttle {
+            spacing,
+            max_wait,
+            hosts: Mutex::default(),
+        }
+    }
+
+    /// Books the next turn to fetch from `host`, returning when it is.
+    ///
+    /// Fails with how long until the host is free again when that is more than `max_wait` away,
+    /// without booking anything.
+    fn reserve(&self, host: &str, now: Instant) -> Result<Instant, Duration> {
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts.retain(|_, free| *free > now);
+        let at = hosts.get(host).copied().unwrap_or(now);
+        if at - now > self.max_wait {
+            return Err(at - now);
+        }
+        hosts.insert(host.to_string(), at + self.spacing);
+        Ok(at)
+    }
+
+    /// Keeps everyone from fetching from `host` until `until`, as it asked with `Retry-After`.
+    fn back_off(&self, host: &str, until: Instant) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let free = hosts.entry(host.to_string()).or_insert(until);
+        *free = (*free).max(until);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fetches_from_one_host_are_spaced_out() {
+        let throttle = Throttle::new(Duration::from_secs(1), Duration::from_secs(10));
+        let now = Instant::now();
+        assert_eq!(throttle.reserve("a.example", now), Ok(now));
+        assert_eq!(
+            throttle.reserve("a.example", now),
+            Ok(now + Duration::from_secs(1))
+        );
+        assert_eq!(throttle.reserve("b.example", now), Ok(now));
+        // Once its turn has passed, a host can be fetched from right away.
+        let later = now + Duration::from_secs(5);
+        assert_eq!(throttle.reserve("a.example", later), Ok(later));
+    }
+
+    #[test]
+    fn refuses_to_wait_past_retry_after() {
+        let throttle = Throttle::new(Duration::from_secs(1), Duration::from_secs(10));
+        let now = Instant::now();
+        throttle.back_off("a.example", now + Duration::from_secs(60));
+        assert_eq!(
+            throttle.reserve("a.example", now),
+            Err(Duration::from_secs(60))
+        );
+        // A shorter Retry-After does not cut the wait short.
+        throttle.back_off("a.example", now + Duration::from_secs(5));
+        assert_eq!(
+            throttle.reserve("a.example", now + Duration::from_secs(55)),
+            Ok(now + Duration::from_secs(60))
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_addresses_that_are_not_public() {
+        // A server that redirects to itself by its loopback address.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let response = format!(
+                    "HTTP/1.1 302 Found\r\nLocation: http://{addr}/x.torrent\r\n\
+                     Content-Length: 0\r\n\r\n"
+                );
+                let _ = tokio::io::AsyncWriteExt::write_all(&mut stream, response.as_bytes()).await;
+            }
+        });
+        // The first hop is let through by naming the server explicitly.
+        let fetcher = Fetcher::with_client(
+            &Config::from_env(),
+            reqwest::Client::builder().resolve("torrents.test", addr),
+        );
+
+        let fetched = fetcher
+            .fetch(&format!("http://torrents.test:{}/", addr.port()), 1024)
+            .await;
+        assert!(matches!(fetched, Err(FetchError::NotPublic { host }) if host == "127.0.0.1"));
+        let fetched = fetcher.fetch("http://localhost/x.torrent", 1024).await;
+        assert!(matches!(fetched, Err(FetchError::NotPublic { host }) if host == "localhost"));
+        let fetched = fetcher.fetch("http://[::ffff:10.0.0.1]/", 1024).await;
+        assert!(matches!(fetched, Err(FetchError::NotPublic { .. })));
+    }
+
+    #[test]
+    fn reads_retry_after_as_seconds_or_a_date() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_eq!(retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(
+            retry_after("Wed, 21 Oct 2015 07:29:30 GMT", now),
+            Some(Duration::from_secs(90))
+        );
+        assert_eq!(
+            retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after("soon", now), None);
+    }
+}
@@ -0,0 +1,75 @@
+use axum::async_trait;
+use axum::extract::{FromRequest, RequestParts};
+use axum::http::header::{ACCEPT, CONTENT_TYPE};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+use std::convert::Infallible;
+
+/// The serialization format of a response, negotiated from the `Accept` header.
+///
+/// JSON is used unless the client explicitly asks for one of the binary formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Format {
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" => Some(Format::Json),
+            "application/msgpack" | "application/x-msgpack" => Some(Format::MessagePack),
+            "application/cbor" => Some(Format::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Picks the first supported media type listed in an `Accept` header.
+    pub fn from_accept(accept: &str) -> Self {
+        accept
+            .split(',')
+            .filter_map(|range| range.split(';').next())
+            .find_map(|media_type| Format::from_media_type(media_type.trim()))
+            .unwrap_or(Format::Json)
+    }
+
+    /// Like [`Format::respond`], with a status other than `200 OK`.
+    pub fn respond_with<T: Serialize>(self, status: StatusCode, value: T) -> Response {
+        let mut response = self.respond(value);
+        *response.status_mut() = status;
+        response
+    }
+
+    pub fn respond<T: Serialize>(self, value: T) -> Response {
+        let encoded = match self {
+            Format::Json => return Json(value).into_response(),
+            Format::MessagePack => rmp_serde::to_vec_named(&value)
+                .map(|bytes| ("application/msgpack", bytes))
+                .map_err(|_| ()),
+            Format::Cbor => serde_cbor::to_vec(&value)
+                .map(|bytes| ("application/cbor", bytes))
+                .map_err(|_| ()),
+        };
+        match encoded {
+            Ok((content_type, bytes)) => ([(CONTENT_TYPE, content_type)], bytes).into_response(),
+            Err(()) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Format {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let accept = req
+            .headers()
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or_default();
+        Ok(Format::from_accept(accept))
+    }
+}
@@ -0,0 +1,177 @@
+use crate::bencode;
+use crate::torrent::Info;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+/// The v1 info-hash is the SHA-1 of the bencoded `info` dictionary,
+/// exactly as it appears in the metainfo file.
+pub(crate) fn info_hash_v1(raw: &[u8]) -> Option<[u8; 20]> {
+    let info = bencode::info_slice(raw)?;
+    Some(Sha1::digest(info).into())
+}
+
+/// The v2 info-hash is the SHA-256 of the same bytes.
+pub(crate) fn info_hash_v2(raw: &[u8]) -> Option<[u8; 32]> {
+    let info = bencode::info_slice(raw)?;
+    Some(Sha256::digest(info).into())
+}
+
+/// A SHA-256 merkle root over the v1 piece hashes, as a fingerprint of the piece set alone.
+///
+/// The tree is built the way BEP 52 builds its piece layers, though nothing in BitTorrent
+/// defines this root:
+/// - Each leaf is the SHA-256 of one 20-byte piece hash, in piece order.
+/// - The leaves are padded with all-zero 32-byte hashes up to the next power of two.
+/// - Each parent is the SHA-256 of its left child's hash followed by its right child's.
+/// - A single piece's root is its leaf. There is no root without any pieces.
+///
+/// Two torrents share this root exactly when they have the same pieces in the same order,
+/// whatever their names, trackers or file lists.
+pub(crate) fn pieces_merkle_root<'a>(pieces: impl Iterator<Item = &'a [u8]>) -> Option<[u8; 32]> {
+    let mut layer: Vec<[u8; 32]> = pieces.map(|piece| Sha256::digest(piece).into()).collect();
+    if layer.is_empty() {
+        return None;
+    }
+    layer.resize(layer.len().next_power_of_two(), [0; 32]);
+    while layer.len() > 1 {
+        layer = layer
+            .chunks_exact(2)
+            .map(|pair| {
+                let mut hasher = Sha256::new();
+                hasher.update(pair[0]);
+                hasher.update(pair[1]);
+                hasher.finalize().into()
+            })
+            .collect();
+    }
+    Some(layer[0])
+}
+
+/// Encodes bytes as RFC 4648 base32, without padding.
+///
+/// A 20-byte v1 info-hash encodes to exactly 32 characters, the form older magnet links use.
+pub(crate) fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+    let mut encoded = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u16;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+/// Decodes RFC 4648 base32, case-insensitively and ignoring padding.
+pub(crate) fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() * 5 / 8);
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for c in encoded.trim_end_matches('=').bytes() {
+        let value = match c.to_ascii_uppercase() {
+            c @ b'A'..=b'Z' => c - b'A',
+            c @ b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        buffer = (buffer << 5) | value as u16;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+/// Which info-hashes should be computed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum HashSelection {
+    V1,
+    V2,
+    #[default]
+    Both,
+}
+
+/// The hex encoded info-hashes of a torrent.
+#[derive(Debug, Serialize)]
+pub(crate) struct InfoHashes {
+    pub v1: Option<String>,
+    pub v2: Option<String>,
+    /// Why a requested hash is missing.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<&'static str>,
+}
+
+impl InfoHashes {
+    pub fn compute(raw: &[u8], info: &Info, selection: HashSelection) -> Self {
+        Self::compute_for(raw, info.has_v1(), info.has_v2(), selection)
+    }
+
+    fn compute_for(raw: &[u8], has_v1: bool, has_v2: bool, selection: HashSelection) -> Self {
+        let mut notes = Vec::new();
+        let mut v1 = None;
+        let mut v2 = None;
+
+        if selection != HashSelection::V2 {
+            if has_v1 {
+                v1 = info_hash_v1(raw).map(hex::encode);
+            } else {
+                notes.push("Not a v1 torrent, so it has no v1 info-hash");
+            }
+        }
+        if selection != HashSelection::V1 {
+            if has_v2 {
+                v2 = info_hash_v2(raw).map(hex::encode);
+            } else {
+                notes.push("Not a v2 torrent, so it has no v2 info-hash");
+            }
+        }
+
+        InfoHashes { v1, v2, notes }
+    }
+}
+
+/// Just the info-hashes and name of a torrent, for when nothing else is needed.
+#[derive(Debug, Serialize)]
+pub(crate) struct HashOnly {
+    pub name: Option<String>,
+    pub info_hash: InfoHashes,
+}
+
+impl HashOnly {
+    /// Finds the info dictionary in the raw bytes and hashes it, without deserializing anything
+    /// but its top-level keys. A file list of any length is only skipped over, never parsed.
+    ///
+    /// Which versions the torrent is for is told the same way as by [`Info::version`].
+    pub fn scan(raw: &[u8], selection: HashSelection) -> Option<Self> {
+        let info = bencode::info_slice(raw)?;
+        let entries = bencode::dict_entries(info)?;
+        let value = |key: &[u8]| {
+            entries
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, range)| &info[range.clone()])
+        };
+
+        let has_v1 = value(b"length").is_some() || value(b"files").is_some();
+        let has_v2 = value(b"meta version").and_then(bencode::as_int) == Some(2)
+            && value(b"file tree").is_some();
+        let name = value(b"name")
+            .and_then(bencode::as_bytes)
+            .map(|name| String::from_utf8_lossy(name).into_owned());
+        Some(HashOnly {
+            name,
+            info_hash: InfoHashes::compute_for(raw, has_v1, has_v2, selection),
+        })
+    }
+}
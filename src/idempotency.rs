@@ -0,0 +1,410 @@
+//! Replaying the response to a retried upload instead of processing it twice.
+
+use crate::error::{AppError, ErrorCode};
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::ConnectInfo;
+use axum::http::header::{ACCEPT, CONTENT_TYPE};
+use axum::http::request::Parts;
+use axum::http::{HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The header a client sets to the same value on every retry of a request.
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+/// Set on replayed responses.
+const REPLAYED: &str = "idempotent-replayed";
+/// The most keys kept at once, so clients cannot fill up memory with them.
+/// The oldest is evicted to make room for a new one.
+const MAX_ENTRIES: usize = 10_000;
+
+/// Keys are per client IP, so that one client cannot replay a response meant for another.
+/// `None` for requests without a peer address, which only happens when testing the router.
+type Key = (Option<IpAddr>, String);
+
+/// Responses by their idempotency key.
+#[derive(Debug)]
+pub(crate) struct Idempotency {
+    /// How long a response is replayed for, or `None` to keep them for good.
+    ttl: Option<Duration>,
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    by_key: HashMap<Key, Entry>,
+    /// Counts up with every entry, to tell the oldest apart without reading the clock.
+    next_id: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    id: u64,
+    /// When the entry was made, only with a TTL.
+    at: Option<Instant>,
+    /// The SHA-256 of the request, see [`digest`], to refuse the key for a different one.
+    digest: [u8; 32],
+    /// `None` while the first request is still being handled.
+    response: Option<Cached>,
+}
+
+#[derive(Debug, Clone)]
+struct Cached {
+    status: StatusCode,
+    content_type: Option<HeaderValue>,
+    body: Bytes,
+}
+
+/// What to do with a request carrying a key.
+enum Claim {
+    /// The key is new: handle the request, then [`Idempotency::complete`] the entry.
+    Run(u64),
+    Replay(Cached),
+    InFlight,
+    Reused,
+}
+
+impl Idempotency {
+    pub fn new(ttl: Option<Duration>) -> Self {
+        Self::with_capacity(ttl, MAX_ENTRIES)
+    }
+
+    fn with_capacity(ttl: Option<Duration>, capacity: usize) -> Self {
+        Idempotency {
+            ttl,
+            capacity,
+            entries: Mutex::default(),
+        }
+    }
+
+    fn is_expired(&self, entry: &Entry) -> bool {
+        self.ttl
+            .zip(entry.at)
+            .is_some_and(|(ttl, at)| at.elapsed() > ttl)
+    }
+
+    /// Looks the key up, marking it in flight if it is new.
+    fn claim(&self, key: &Key, digest: [u8; 32]) -> Claim {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.by_key.get(key).filter(|e| !self.is_expired(e)) {
+            return match &entry.response {
+                _ if entry.digest != digest => Claim::Reused,
+                Some(cached) => Claim::Replay(cached.clone()),
+                None => Claim::InFlight,
+            };
+        }
+
+        entries.by_key.retain(|_, entry| !self.is_expired(entry));
+        while entries.by_key.len() >= self.capacity {
+            let oldest = entries
+                .by_key
+                .iter()
+                .min_by_key(|(_, entry)| entry.id)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => entries.by_key.remove(&oldest),
+                None => break,
+            };
+        }
+        let id = entries.next_id;
+        entries.next_id += 1;
+        entries.by_key.insert(
+            key.clone(),
+            Entry {
+                id,
+                at: self.ttl.map(|_| Instant::now()),
+                digest,
+                response: None,
+            },
+        );
+        Claim::Run(id)
+    }
+
+    /// Keeps the response of the request that claimed entry `id`.
+    fn complete(&self, key: &Key, id: u64, cached: Cached) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.by_key.get_mut(key).filter(|e| e.id == id) {
+            entry.response = Some(cached);
+        }
+    }
+
+    /// Forgets entry `id` while it is still in flight, so the request can be retried.
+    fn release(&self, key: &Key, id: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries
+            .by_key
+            .get(key)
+            .is_some_and(|e| e.id == id && e.response.is_none())
+        {
+            entries.by_key.remove(key);
+        }
+    }
+}
+
+/// Releases an entry left in flight, whether the request failed or its handler never finished.
+struct InFlight<'a> {
+    idempotency: &'a Idempotency,
+    key: &'a Key,
+    id: u64,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.idempotency.release(self.key, self.id);
+    }
+}
+
+/// Answers a request carrying an `Idempotency-Key` that was seen before with the response
+/// it got then, without running the handler again.
+///
+/// A key is only replayed to the client IP that sent it, and only for the same request: the
+/// same query string and `Accept` header, and the same body byte for byte, so a retry has to
+/// reuse its multipart boundary. A different request is refused with 422. A retry that arrives
+/// while the first request is still being handled is refused with 409. Only successful responses are kept, so a request that
+/// failed, say because of the quota, can be retried with the same key.
+pub(crate) async fn idempotent(request: Request<Body>, next: Next<Body>) -> Response {
+    let idempotency = request.extensions().get::<Arc<Idempotency>>().cloned();
+    let key = request
+        .headers()
+        .get(IDEMPOTENCY_KEY)
+        .and_then(|key| key.to_str().ok())
+        .map(String::from);
+    let (Some(idempotency), Some(key)) = (idempotency, key) else {
+        return next.run(request).await;
+    };
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let key = (ip, key);
+
+    let (parts, body) = request.into_parts();
+    let Some(body) = collect(body).await else {
+        return AppError::new(ErrorCode::UploadUnreadable, "Failed to read upload").into_response();
+    };
+    let digest = digest(&parts, &body);
+    let request = Request::from_parts(parts, Body::from(body));
+
+    let id = match idempotency.claim(&key, digest) {
+        Claim::Run(id) => id,
+        Claim::Replay(cached) => {
+            let mut response = (cached.status, cached.body).into_response();
+            if let Some(content_type) = cached.content_type {
+                response.headers_mut().insert(CONTENT_TYPE, content_type);
+            }
+            response
+                .headers_mut()
+                .insert(REPLAYED, HeaderValue::from_static("true"));
+            return response;
+        }
+        Claim::InFlight => {
+            return AppError::new(
+                ErrorCode::IdempotencyKeyInFlight,
+                "A request with this Idempotency-Key is still being handled",
+            )
+            .into_response()
+        }
+        Claim::Reused => {
+            return AppError::new(
+                ErrorCode::IdempotencyKeyReused,
+                "This Idempotency-Key was already used for a different request",
+            )
+            .into_response()
+        }
+    };
+    let _in_flight = InFlight {
+        idempotency: &idempotency,
+        key: &key,
+        id,
+    };
+
+    let response = next.run(request).await;
+    if !response.status().is_success() {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let Some(body) = collect(body).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    idempotency.complete(
+        &key,
+        id,
+        Cached {
+            status: parts.status,
+            content_type: parts.headers.get(CONTENT_TYPE).cloned(),
+            body: body.clone(),
+        },
+    );
+    Response::from_parts(parts, axum::body::boxed(axum::body::Full::new(body)))
+}
+
+/// The SHA-256 of everything that shapes the response to a request: its query string, which
+/// carries options like `save_as`, its `Accept` header, which picks the format, and its body.
+fn digest(parts: &Parts, body: &[u8]) -> [u8; 32] {
+    let query = parts.uri.query().unwrap_or_default().as_bytes();
+    let accept = parts
+        .headers
+        .get(ACCEPT)
+        .map(HeaderValue::as_bytes)
+        .unwrap_or_default();
+    let mut sha256 = Sha256::new();
+    // Each part is prefixed with its length, so that moving bytes from one to the next counts.
+    for part in [query, accept, body] {
+        sha256.update((part.len() as u64).to_be_bytes());
+        sha256.update(part);
+    }
+    sha256.finalize().into()
+}
+
+/// Reads a whole body, or `None` if it fails part way.
+async fn collect<B: HttpBody<Data = Bytes> + Unpin>(mut body: B) -> Option<Bytes> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        bytes.extend_from_slice(&chunk.ok()?);
+    }
+    Some(Bytes::from(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::post;
+    use axum::{middleware, Extension, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::Notify;
+    use tower::ServiceExt;
+
+    /// A router counting how often its handler ran, answering with the count.
+    fn app(idempotency: Idempotency) -> (Router, Arc<AtomicUsize>) {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let router = Router::new()
+            .route(
+                "/",
+                post(
+                    move || async move { (counter.fetch_add(1, Ordering::SeqCst) + 1).to_string() },
+                )
+                .layer(middleware::from_fn(idempotent)),
+            )
+            .layer(Extension(Arc::new(idempotency)));
+        (router, runs)
+    }
+
+    fn request(key: &str, ip: [u8; 4], body: &'static str) -> Request<Body> {
+        let mut request = Request::post("/")
+            .header(IDEMPOTENCY_KEY, key)
+            .body(Body::from(body))
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((ip, 1234))));
+        request
+    }
+
+    async fn send(router: Router, request: Request<Body>) -> (StatusCode, bool, Bytes) {
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let replayed = response.headers().contains_key(REPLAYED);
+        (
+            status,
+            replayed,
+            collect(response.into_body()).await.unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn replays_the_same_request() {
+        let (router, runs) = app(Idempotency::new(None));
+        let first = send(router.clone(), request("a", [10, 0, 0, 1], "body")).await;
+        let second = send(router.clone(), request("a", [10, 0, 0, 1], "body")).await;
+        assert_eq!(first, (StatusCode::OK, false, Bytes::from("1")));
+        assert_eq!(second, (StatusCode::OK, true, Bytes::from("1")));
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn refuses_the_key_for_a_different_body() {
+        let (router, runs) = app(Idempotency::new(None));
+        send(router.clone(), request("a", [10, 0, 0, 1], "body")).await;
+        let (status, ..) = send(router.clone(), request("a", [10, 0, 0, 1], "other")).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn refuses_the_key_for_another_format_or_query() {
+        let (router, runs) = app(Idempotency::new(None));
+        send(router.clone(), request("a", [10, 0, 0, 1], "body")).await;
+
+        let mut msgpack = request("a", [10, 0, 0, 1], "body");
+        msgpack
+            .headers_mut()
+            .insert(ACCEPT, HeaderValue::from_static("application/msgpack"));
+        let (status, ..) = send(router.clone(), msgpack).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let mut save_as = request("a", [10, 0, 0, 1], "body");
+        *save_as.uri_mut() = "/?save_as=x".parse().unwrap();
+        let (status, ..) = send(router.clone(), save_as).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn keys_are_per_client() {
+        let (router, runs) = app(Idempotency::new(None));
+        send(router.clone(), request("a", [10, 0, 0, 1], "body")).await;
+        let other = send(router.clone(), request("a", [10, 0, 0, 2], "body")).await;
+        assert_eq!(other, (StatusCode::OK, false, Bytes::from("2")));
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn refuses_a_retry_while_in_flight() {
+        let started = Arc::new(Notify::new());
+        let finish = Arc::new(Notify::new());
+        let (on_start, on_finish) = (started.clone(), finish.clone());
+        let router = Router::new()
+            .route(
+                "/",
+                post(move || async move {
+                    on_start.notify_one();
+                    on_finish.notified().await;
+                    "done"
+                })
+                .layer(middleware::from_fn(idempotent)),
+            )
+            .layer(Extension(Arc::new(Idempotency::new(None))));
+
+        let first = tokio::spawn({
+            let router = router.clone();
+            async move { send(router.clone(), request("a", [10, 0, 0, 1], "body")).await }
+        });
+        started.notified().await;
+        let (status, ..) = send(router.clone(), request("a", [10, 0, 0, 1], "body")).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        finish.notify_one();
+        assert_eq!(first.await.unwrap().0, StatusCode::OK);
+        let (status, replayed, _) = send(router.clone(), request("a", [10, 0, 0, 1], "body")).await;
+        assert_eq!((status, replayed), (StatusCode::OK, true));
+    }
+
+    #[tokio::test]
+    async fn evicts_the_oldest_key_when_full() {
+        let (router, runs) = app(Idempotency::with_capacity(None, 2));
+        for key in ["a", "b", "c"] {
+            send(router.clone(), request(key, [10, 0, 0, 1], "body")).await;
+        }
+        let (_, replayed, _) = send(router.clone(), request("c", [10, 0, 0, 1], "body")).await;
+        assert!(replayed);
+        let (_, replayed, _) = send(router.clone(), request("a", [10, 0, 0, 1], "body")).await;
+        assert!(!replayed);
+        assert_eq!(runs.load(Ordering::SeqCst), 4);
+    }
+}
@@ -0,0 +1,369 @@
+use crate::bencode;
+use crate::client::Client;
+use crate::crossseed::{self, CrossSeedNote};
+use crate::dates;
+use crate::hash::{self, HashSelection, InfoHashes};
+use crate::links;
+use crate::pieces::{self, PieceLengthRecommendation};
+use crate::sanitize::{self, TargetOs};
+use crate::timing::Timings;
+use crate::torrent::{AddressType, Torrent, Version};
+use crate::trackers::{Tier, TrackerStats};
+use crate::validate::{self, Warning};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Query parameters tuning what an inspection computes.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub(crate) struct InspectOptions {
+    pub hash: HashSelection,
+    /// The system `name_canonical` is sanitized for.
+    pub os: TargetOs,
+    pub mode: Mode,
+    /// Whether to include the [`Offsets`] of key fields.
+    pub offsets: bool,
+    /// The time `creation_date_relative` is relative to, in seconds since the Unix epoch.
+    /// It is left out when this is unset.
+    #[serde(skip)]
+    pub now: Option<u64>,
+    /// A bandwidth in megabits per second to estimate the download time at.
+    pub mbps: Option<f64>,
+}
+
+/// How much of a torrent to look at.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Mode {
+    /// Parse and inspect everything.
+    #[default]
+    Full,
+    /// Only compute the info-hashes and read the name, see [`crate::hash::HashOnly`].
+    Hash,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Md5sum {
+    pub path: String,
+    pub md5sum: String,
+}
+
+/// How many files have an extension, and how large they are together.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct ExtensionStat {
+    pub count: usize,
+    pub total_size: u64,
+}
+
+/// The bucket of [`extension_stats`] for files without an extension.
+const NO_EXTENSION: &str = "(none)";
+
+/// Groups the files by the lowercased extension of their last path segment.
+///
+/// Dotfiles like `.nfo` on their own and names ending in a dot count as having no extension.
+fn extension_stats(torrent: &Torrent) -> BTreeMap<String, ExtensionStat> {
+    let mut stats: BTreeMap<String, ExtensionStat> = BTreeMap::new();
+    for file in torrent.info.file_entries() {
+        let name = file.path.last().copied().unwrap_or_default();
+        let extension = match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() && !extension.is_empty() => {
+                extension.to_lowercase()
+            }
+            _ => NO_EXTENSION.to_string(),
+        };
+        let stat = stats.entry(extension).or_default();
+        stat.count += 1;
+        stat.total_size = stat.total_size.saturating_add(file.length);
+    }
+    stats
+}
+
+/// The size of the v2 **piece layers**.
+#[derive(Debug, Serialize)]
+pub(crate) struct PieceLayersSummary {
+    /// The number of files with a layer.
+    pub count: usize,
+    pub total_bytes: usize,
+}
+
+/// How long downloading the whole torrent takes at a given bandwidth, from `?mbps=`.
+///
+/// Ignores protocol overhead and assumes the swarm can keep up.
+/// There is no estimate for a bandwidth that is not positive.
+#[derive(Debug, Serialize)]
+pub(crate) struct EstimatedDownload {
+    pub mbps: f64,
+    pub seconds: Option<u64>,
+    /// `seconds` like `1h 2m 3s`.
+    pub duration: Option<String>,
+}
+
+impl EstimatedDownload {
+    pub fn new(total_size: u64, mbps: f64) -> Self {
+        let seconds = (mbps.is_finite() && mbps > 0.0)
+            .then(|| (total_size as f64 * 8.0 / (mbps * 1_000_000.0)).ceil() as u64);
+        EstimatedDownload {
+            mbps,
+            seconds,
+            duration: seconds.map(dates::human_duration),
+        }
+    }
+}
+
+/// Where key fields are in the raw file, for debugging parsers.
+///
+/// Each span covers the whole bencoded value, including the length prefix of a string, from the
+/// offset of its first byte up to but not including `end`. Fields that are missing are `None`.
+#[derive(Debug, Serialize)]
+pub(crate) struct Offsets {
+    /// The size of the whole file.
+    pub length: usize,
+    pub info: Option<Span>,
+    pub announce: Option<Span>,
+    /// **pieces** of the info dictionary.
+    pub pieces: Option<Span>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Offsets {
+    pub fn new(raw: &[u8]) -> Self {
+        let entries = bencode::dict_entries(raw).unwrap_or_default();
+        let find = |entries: &[(&[u8], Range<usize>)], key: &[u8]| {
+            entries
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, range)| range.clone())
+        };
+        let info = find(&entries, b"info");
+        let pieces = info.clone().and_then(|info| {
+            let entries = bencode::dict_entries(&raw[info.clone()])?;
+            let pieces = find(&entries, b"pieces")?;
+            Some(info.start + pieces.start..info.start + pieces.end)
+        });
+        let span = |range: Range<usize>| Span {
+            start: range.start,
+            end: range.end,
+        };
+        Offsets {
+            length: raw.len(),
+            info: info.map(span),
+            announce: find(&entries, b"announce").map(span),
+            pieces: pieces.map(span),
+        }
+    }
+}
+
+/// A DHT node from **nodes**, with the kind of address it has.
+#[derive(Debug, Serialize)]
+pub(crate) struct DhtNode {
+    pub host: String,
+    pub port: i64,
+    /// `None` when the host is not a valid address.
+    pub address_type: Option<AddressType>,
+}
+
+/// The result of inspecting a torrent: the metainfo itself, plus what we found out about it.
+#[derive(Debug, Serialize)]
+pub(crate) struct Inspection {
+    #[serde(flatten)]
+    pub torrent: Torrent,
+    /// **name** as a client on the requested OS would create it.
+    pub name_canonical: String,
+    /// Whether `name_canonical` differs from **name**.
+    pub name_sanitized: bool,
+    pub info_hash: InfoHashes,
+    /// The v1 info-hash in base32, as understood by clients that do not take hex.
+    pub info_hash_base32: Option<String>,
+    /// **creation date** relative to the time of inspection, like `3 years ago`.
+    pub creation_date_relative: Option<String>,
+    /// The URLs found in **comment**, such as where a release came from.
+    pub comment_links: Vec<String>,
+    /// The client parsed from **created by**, when it is a known one.
+    pub client: Option<Client>,
+    /// `None` if the file lengths overflow, see [`Warning::SizeOverflow`].
+    pub total_size: Option<u64>,
+    /// Only with `?mbps=`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_download: Option<EstimatedDownload>,
+    pub file_count: usize,
+    /// See [`extension_stats`].
+    pub extension_stats: BTreeMap<String, ExtensionStat>,
+    /// The number of whole v1 piece hashes.
+    pub piece_count: usize,
+    /// See [`hash::pieces_merkle_root`], in hex. `None` without v1 pieces.
+    pub pieces_merkle_root: Option<String>,
+    /// **piece length** in binary units, like `256 KiB`.
+    pub piece_length_human: String,
+    /// log2 of **piece length**, like `18` for 256 KiB, or `None` if it is not a power of two.
+    pub piece_length_exponent: Option<u32>,
+    /// `None` without a `total_size`.
+    pub piece_length_recommendation: Option<PieceLengthRecommendation>,
+    pub piece_layers: PieceLayersSummary,
+    /// The well-formed MD5 sums by file path, lowercased.
+    pub md5sums: Vec<Md5sum>,
+    pub dht_nodes: Vec<DhtNode>,
+    /// The distinct trackers across **announce** and **announce-list**, up to [`MAX_TRACKERS`].
+    pub trackers: Vec<String>,
+    /// How many more distinct trackers there are than listed in `trackers`.
+    pub trackers_omitted: usize,
+    pub tracker_stats: TrackerStats,
+    /// The trackers a client actually uses: **announce** is ignored when **announce-list** is present.
+    pub effective_trackers: Vec<String>,
+    /// The tiers those trackers are tried in, each marked as primary or backup.
+    /// **announce-list** itself is left as it is.
+    pub tiers: Vec<Tier>,
+    /// See [`Torrent::is_discoverable`].
+    pub discoverable: bool,
+    /// **announce-list** with its empty tiers and URLs removed, if it has any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cleaned_announce_list: Option<Vec<Vec<String>>>,
+    /// The fields that cross-seeding this torrent with copies from other trackers comes down to.
+    pub cross_seed_notes: Vec<CrossSeedNote>,
+    /// Only with `?offsets=true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offsets: Option<Offsets>,
+    pub warnings: Vec<Warning>,
+}
+
+/// The most trackers listed in an inspection. Some torrents carry thousands of them.
+pub(crate) const MAX_TRACKERS: usize = 200;
+
+impl Inspection {
+    pub fn new(torrent: Torrent, raw: &[u8], options: &InspectOptions) -> Self {
+        Self::timed(torrent, raw, options, &mut Timings::default())
+    }
+
+    /// Like [`Inspection::new`], timing validation and hashing as `validate` and `hash`.
+    pub fn timed(
+        mut torrent: Torrent,
+        raw: &[u8],
+        options: &InspectOptions,
+        timings: &mut Timings,
+    ) -> Self {
+        torrent.info.resolve_priorities();
+        let all_trackers = torrent.trackers();
+        let trackers_omitted = all_trackers.len().saturating_sub(MAX_TRACKERS);
+        let trackers = all_trackers
+            .into_iter()
+            .take(MAX_TRACKERS)
+            .map(String::from)
+            .collect();
+        let warnings = timings.time("validate", || {
+            let mut warnings = validate::validate(&torrent);
+            warnings.extend(validate::check_trailing(raw));
+            warnings
+        });
+        let client = torrent.created_by.as_deref().and_then(Client::classify);
+        let name_canonical = sanitize::sanitize(&torrent.info.name, options.os);
+        let (info_hash, pieces_merkle_root) = timings.time("hash", || {
+            (
+                InfoHashes::compute(raw, &torrent.info, options.hash),
+                hash::pieces_merkle_root(torrent.info.piece_hashes()).map(hex::encode),
+            )
+        });
+        let total_size = torrent.info.total_size();
+        let info_hash_base32 = info_hash
+            .v1
+            .as_ref()
+            .and_then(|v1| hex::decode(v1).ok())
+            .map(|v1| hash::base32(&v1));
+        Inspection {
+            name_sanitized: name_canonical != torrent.info.name,
+            name_canonical,
+            info_hash,
+            info_hash_base32,
+            total_size,
+            estimated_download: options
+                .mbps
+                .zip(total_size)
+                .map(|(mbps, total_size)| EstimatedDownload::new(total_size, mbps)),
+            file_count: torrent.info.file_count(),
+            extension_stats: extension_stats(&torrent),
+            piece_count: torrent.info.piece_hashes().count(),
+            pieces_merkle_root,
+            piece_length_human: pieces::human_size(torrent.info.piece_length.max(0) as u64),
+            piece_length_exponent: pieces::length_exponent(torrent.info.piece_length),
+            piece_length_recommendation: total_size.map(|total_size| {
+                PieceLengthRecommendation::new(total_size, torrent.info.piece_length)
+            }),
+            piece_layers: PieceLayersSummary {
+                count: torrent.piece_layers.len(),
+                total_bytes: torrent.piece_layers.values().map(|layer| layer.len()).sum(),
+            },
+            md5sums: torrent
+                .info
+                .md5sums()
+                .into_iter()
+                .filter(|(_, md5sum)| validate::is_md5sum(md5sum))
+                .map(|(path, md5sum)| Md5sum {
+                    path,
+                    md5sum: md5sum.to_ascii_lowercase(),
+                })
+                .collect(),
+            dht_nodes: torrent
+                .nodes
+                .iter()
+                .map(|node| DhtNode {
+                    host: node.0.clone(),
+                    port: node.1,
+                    address_type: node.address_type(),
+                })
+                .collect(),
+            trackers,
+            trackers_omitted,
+            tracker_stats: TrackerStats::new(&torrent),
+            effective_trackers: torrent.effective_trackers(),
+            tiers: Tier::list(&torrent),
+            discoverable: torrent.is_discoverable(),
+            cleaned_announce_list: torrent.cleaned_announce_list(),
+            cross_seed_notes: crossseed::notes(&torrent),
+            offsets: options.offsets.then(|| Offsets::new(raw)),
+            comment_links: torrent
+                .comment
+                .as_deref()
+                .map(links::links)
+                .unwrap_or_default(),
+            creation_date_relative: torrent
+                .creation_date
+                .zip(options.now)
+                .map(|(date, now)| dates::relative(date, now)),
+            torrent,
+            client,
+            warnings,
+        }
+    }
+}
+
+/// The few facts about a torrent that fit on a dashboard tile.
+#[derive(Debug, Serialize)]
+pub(crate) struct Summary {
+    pub name: String,
+    /// `None` if the file lengths overflow.
+    pub total_size: Option<u64>,
+    pub file_count: usize,
+    pub piece_count: usize,
+    pub tracker_count: usize,
+    pub version: Option<Version>,
+    #[serde(rename = "creation date")]
+    pub creation_date: Option<u64>,
+}
+
+impl Summary {
+    pub fn new(torrent: Torrent) -> Self {
+        Summary {
+            total_size: torrent.info.total_size(),
+            file_count: torrent.info.file_count(),
+            piece_count: torrent.info.piece_hashes().count(),
+            tracker_count: torrent.trackers().len(),
+            version: torrent.info.version(),
+            creation_date: torrent.creation_date,
+            name: torrent.info.name,
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! Checking torrents against a list of info-hashes known to be good, for spotting fakes.
+
+use crate::hash::InfoHashes;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// Known info-hashes, each with an optional label, read from a text file.
+///
+/// Every line of the file holds a hex info-hash, optionally followed by whitespace and a label.
+/// Blank lines and lines starting with `#` are skipped.
+#[derive(Debug)]
+pub(crate) struct KnownHashes {
+    file: Option<PathBuf>,
+    hashes: RwLock<HashMap<String, Option<String>>>,
+}
+
+/// Whether a torrent is in the known set.
+#[derive(Debug, Serialize)]
+pub(crate) struct KnownMatch {
+    pub info_hash: InfoHashes,
+    pub known: bool,
+    /// Which of the torrent's info-hashes is known.
+    pub matched: Option<String>,
+    pub label: Option<String>,
+}
+
+impl KnownHashes {
+    /// Reads the set from `file`. It is empty when there is no file or it cannot be read.
+    pub fn new(file: Option<PathBuf>) -> Self {
+        let known = KnownHashes {
+            file,
+            hashes: RwLock::default(),
+        };
+        let _ = known.reload();
+        known
+    }
+
+    /// Reads the file again, replacing the set with it, and returns how many hashes it has.
+    ///
+    /// The set is left as it was when the file cannot be read.
+    pub fn reload(&self) -> io::Result<usize> {
+        let Some(file) = &self.file else {
+            return Ok(0);
+        };
+        let hashes: HashMap<String, Option<String>> = std::fs::read_to_string(file)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| match line.split_once(char::is_whitespace) {
+                Some((hash, label)) => (hash.to_ascii_lowercase(), Some(label.trim().to_string())),
+                None => (line.to_ascii_lowercase(), None),
+            })
+            .collect();
+        let count = hashes.len();
+        *self.hashes.write().unwrap() = hashes;
+        Ok(count)
+    }
+
+    /// Looks up the v1 and then the v2 info-hash of a torrent.
+    pub fn check(&self, info_hash: InfoHashes) -> KnownMatch {
+        let hashes = self.hashes.read().unwrap();
+        let found = [&info_hash.v1, &info_hash.v2]
+            .into_iter()
+            .flatten()
+            .find_map(|hash| Some((hash.clone(), hashes.get(hash)?.clone())));
+        drop(hashes);
+        let (matched, label) = found.unzip();
+        KnownMatch {
+            known: matched.is_some(),
+            matched,
+            label: label.flatten(),
+            info_hash,
+        }
+    }
+}
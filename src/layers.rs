@@ -0,0 +1,106 @@
+//! The v2 merkle hashes of each file, for verifying content against a v2 torrent.
+
+use crate::torrent::Torrent;
+use serde::{Deserialize, Serialize};
+
+/// Which files to list, as query parameters.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct Page {
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Page {
+            offset: 0,
+            limit: 100,
+        }
+    }
+}
+
+/// The most files listed at once, whatever `limit` asks for.
+const MAX_LIMIT: usize = 1000;
+
+/// A page of the files of a v2 **file tree**, in tree order.
+#[derive(Debug, Serialize)]
+pub(crate) struct PieceLayers {
+    /// The number of files in the whole tree.
+    pub total: usize,
+    pub offset: usize,
+    pub files: Vec<FileLayer>,
+}
+
+/// The hashes of a single file.
+#[derive(Debug, Serialize)]
+pub(crate) struct FileLayer {
+    pub path: String,
+    pub length: u64,
+    /// Absent for empty files.
+    pub pieces_root: Option<String>,
+    /// How many piece hashes the layer must have: one per piece, or none for files
+    /// no larger than a single piece, whose pieces root is the only hash needed.
+    pub expected: u64,
+    /// The SHA-256 hashes of the file's pieces, from **piece layers**, up to the display limit.
+    pub hashes: Vec<String>,
+    /// The number of whole hashes in the layer.
+    pub hash_count: usize,
+    /// Whether `hashes` stops short of `hash_count`.
+    pub truncated: bool,
+    /// Bytes left over after the last whole hash of the layer, which should be none.
+    pub remainder: usize,
+    /// Whether the layer has exactly the expected hashes.
+    pub complete: bool,
+}
+
+impl PieceLayers {
+    /// Lists the files of a v2 torrent, or `None` if it has no **file tree**.
+    ///
+    /// Each file lists up to `hash_limit` of its hashes, or all of them when `None`.
+    pub fn new(torrent: &Torrent, page: &Page, hash_limit: Option<usize>) -> Option<Self> {
+        torrent.info.file_tree.as_ref()?;
+        let piece_length = torrent.info.piece_length.max(1) as u64;
+        let files = torrent.info.file_tree_files();
+        let total = files.len();
+        let files = files
+            .into_iter()
+            .skip(page.offset)
+            .take(page.limit.min(MAX_LIMIT))
+            .map(|(path, file)| {
+                let layer = file
+                    .pieces_root
+                    .as_ref()
+                    .and_then(|root| torrent.piece_layers.get(serde_bytes::Bytes::new(root)))
+                    .map(|layer| layer.as_slice())
+                    .unwrap_or_default();
+                let expected = match file.length {
+                    length if length > piece_length => length.div_ceil(piece_length),
+                    _ => 0,
+                };
+                let hash_count = layer.len() / 32;
+                let hashes: Vec<String> = layer
+                    .chunks_exact(32)
+                    .take(hash_limit.unwrap_or(usize::MAX))
+                    .map(hex::encode)
+                    .collect();
+                FileLayer {
+                    path: path.join("/"),
+                    length: file.length,
+                    pieces_root: file.pieces_root.as_deref().map(hex::encode),
+                    expected,
+                    complete: hash_count as u64 == expected && layer.len() % 32 == 0,
+                    remainder: layer.len() % 32,
+                    truncated: hashes.len() < hash_count,
+                    hash_count,
+                    hashes,
+                }
+            })
+            .collect();
+        Some(PieceLayers {
+            total,
+            offset: page.offset,
+            files,
+        })
+    }
+}
@@ -0,0 +1,126 @@
+//! The files a client creates on disk when it downloads a torrent.
+
+use crate::sanitize::{self, TargetOs};
+use crate::torrent::Info;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Query parameters of the layout preview.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct LayoutOptions {
+    pub os: TargetOs,
+}
+
+/// Every file of a download, relative to the download directory.
+#[derive(Debug, Serialize)]
+pub(crate) struct Layout {
+    pub os: TargetOs,
+    pub files: Vec<LayoutFile>,
+    /// Paths that two or more files of the torrent end up at once sanitized.
+    pub collisions: Vec<Collision>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct LayoutFile {
+    /// Where the file is created, with every segment sanitized.
+    pub path: String,
+    /// The path as the torrent lists it, including the torrent name for multi-file torrents.
+    pub original: String,
+    pub length: u64,
+    /// Whether sanitizing changed the path.
+    pub sanitized: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Collision {
+    pub path: String,
+    /// The original paths of the files, or directories, sharing `path`.
+    pub files: Vec<String>,
+}
+
+impl Layout {
+    /// Resolves the paths of a torrent's files for `os`.
+    ///
+    /// Multi-file torrents are created in a directory named after the torrent, single file
+    /// torrents as a file of that name. Padding files are left out, as clients do not create them.
+    /// Paths are compared case-insensitively on Windows and macOS, whose filesystems usually are.
+    pub fn new(info: &Info, os: TargetOs) -> Self {
+        let single_file = info.files.is_none()
+            && matches!(info.file_entries().as_slice(), [file] if file.path == [info.name.as_str()]);
+        let entries: Vec<(Vec<&str>, u64)> = match &info.files {
+            Some(files) => files
+                .iter()
+                .filter(|file| !file.is_padding())
+                .map(|file| (file.path.iter().map(String::as_str).collect(), file.length))
+                .collect(),
+            None => info
+                .file_entries()
+                .into_iter()
+                .map(|file| (file.path, file.length))
+                .collect(),
+        };
+
+        let files: Vec<LayoutFile> = entries
+            .into_iter()
+            .map(|(path, length)| {
+                let mut original = path;
+                if !single_file {
+                    original.insert(0, &info.name);
+                }
+                let path: Vec<String> = original
+                    .iter()
+                    .map(|segment| sanitize::sanitize(segment, os))
+                    .collect();
+                let sanitized = path.iter().zip(&original).any(|(a, b)| a != b);
+                LayoutFile {
+                    path: path.join("/"),
+                    original: original.join("/"),
+                    length,
+                    sanitized,
+                }
+            })
+            .collect();
+
+        let key = |path: &str| match os {
+            TargetOs::Windows | TargetOs::Macos => path.to_lowercase(),
+            TargetOs::Linux => path.to_string(),
+        };
+        let mut claimed: BTreeMap<String, (String, Vec<String>)> = BTreeMap::new();
+        for file in &files {
+            claimed
+                .entry(key(&file.path))
+                .or_insert_with(|| (file.path.clone(), Vec::new()))
+                .1
+                .push(file.original.clone());
+        }
+        // A file also collides with a directory at its path, as it cannot be both.
+        let mut directories = BTreeSet::new();
+        for file in &files {
+            let sanitized: Vec<&str> = file.path.split('/').collect();
+            let original: Vec<&str> = file.original.split('/').collect();
+            for depth in 1..sanitized.len() {
+                directories.insert((
+                    key(&sanitized[..depth].join("/")),
+                    original[..depth].join("/") + "/",
+                ));
+            }
+        }
+        for (directory, original) in directories {
+            if let Some((_, claimants)) = claimed.get_mut(&directory) {
+                claimants.push(original);
+            }
+        }
+        let collisions = claimed
+            .into_values()
+            .filter(|(_, files)| files.len() > 1)
+            .map(|(path, files)| Collision { path, files })
+            .collect();
+
+        Layout {
+            os,
+            files,
+            collisions,
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Finding URLs in free text, like the **comment** of a torrent.
+
+use url::Url;
+
+/// Every distinct `http`, `https` and `magnet` URL in `text`, in order of appearance.
+///
+/// A URL ends at whitespace or a quote or angle bracket, and trailing punctuation that usually
+/// belongs to the sentence around it is dropped, as is a closing parenthesis without an opening one.
+pub(crate) fn links(text: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    let lowercase = text.to_ascii_lowercase();
+    let mut pos = 0;
+    while let Some(start) = ["http://", "https://", "magnet:?"]
+        .iter()
+        .filter_map(|scheme| lowercase[pos..].find(scheme))
+        .min()
+        .map(|start| pos + start)
+    {
+        let len = text[start..]
+            .find(|c: char| c.is_whitespace() || "<>\"'`".contains(c))
+            .unwrap_or(text.len() - start);
+        let mut candidate = &text[start..start + len];
+        loop {
+            let trimmed = candidate.trim_end_matches(['.', ',', ';', ':', '!', '?']);
+            let trimmed = match trimmed.strip_suffix(')') {
+                Some(inner) if inner.matches('(').count() < trimmed.matches(')').count() => inner,
+                _ => trimmed,
+            };
+            if trimmed == candidate {
+                break;
+            }
+            candidate = trimmed;
+        }
+        if Url::parse(candidate).is_ok_and(|url| url.has_host() || url.scheme() == "magnet")
+            && !links.iter().any(|link| link == candidate)
+        {
+            links.push(candidate.to_string());
+        }
+        pos = start + len.max(1);
+    }
+    links
+}
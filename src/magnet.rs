@@ -0,0 +1,180 @@
+use crate::hash::{self, InfoHashes};
+use crate::torrent::Torrent;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Serialize;
+
+/// Characters left as they are in magnet parameters, as they are in URL query components.
+const MAGNET_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Builds a magnet link for the torrent.
+///
+/// Both the v1 (`urn:btih:`) and the v2 (`urn:btmh:`, as a SHA-256 multihash) topics are included
+/// when the torrent has them. `select_only` lists the file indices to put in the BEP 53 `so` parameter.
+pub(crate) fn magnet(torrent: &Torrent, hashes: &InfoHashes, select_only: &[usize]) -> String {
+    let mut params = Vec::new();
+    if let Some(v1) = &hashes.v1 {
+        params.push(format!("xt=urn:btih:{v1}"));
+    }
+    if let Some(v2) = &hashes.v2 {
+        params.push(format!("xt=urn:btmh:1220{v2}"));
+    }
+    params.push(format!("dn={}", encode(&torrent.info.name)));
+    for tracker in torrent.tiers().iter().flatten() {
+        params.push(format!("tr={}", encode(tracker)));
+    }
+    if !select_only.is_empty() {
+        params.push(format!("so={}", ranges(select_only)));
+    }
+    format!("magnet:?{}", params.join("&"))
+}
+
+fn encode(value: &str) -> String {
+    utf8_percent_encode(value, MAGNET_VALUE).to_string()
+}
+
+/// Compresses file indices into the `so` syntax, e.g. `0,1,2,3,7` into `0-3,7`.
+fn ranges(indices: &[usize]) -> String {
+    let mut indices = indices.to_vec();
+    indices.sort_unstable();
+    indices.dedup();
+
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for index in indices {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == index => *end = index,
+            _ => ranges.push((index, index)),
+        }
+    }
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{start}-{end}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Resolves a file selection, given either as an index or as a path relative to the torrent root,
+/// to the file index used by `so`.
+pub(crate) fn resolve_selection(torrent: &Torrent, selection: &str) -> Result<usize, String> {
+    let files = torrent.info.file_entries();
+    let index = match selection.parse::<usize>() {
+        Ok(index) => Some(index).filter(|index| *index < files.len()),
+        Err(_) => {
+            let selection = selection.trim_matches('/');
+            files
+                .iter()
+                .position(|file| file.path.join("/") == selection)
+        }
+    };
+    index.ok_or_else(|| format!("No such file in torrent: {selection}"))
+}
+
+/// The parts of a magnet link that identify a torrent.
+#[derive(Debug, Default)]
+pub(crate) struct MagnetLink {
+    /// The hex encoded v1 info-hash, from `xt=urn:btih:` in either hex or base32.
+    pub v1: Option<String>,
+    /// The hex encoded v2 info-hash, from `xt=urn:btmh:` as a SHA-256 multihash.
+    pub v2: Option<String>,
+    /// The display name, from `dn`.
+    pub name: Option<String>,
+}
+
+impl MagnetLink {
+    pub fn parse(magnet: &str) -> Result<Self, String> {
+        let query = magnet
+            .trim()
+            .strip_prefix("magnet:?")
+            .ok_or_else(|| "Not a magnet link".to_string())?;
+
+        let mut link = MagnetLink::default();
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            match &*key {
+                "xt" => {
+                    if let Some(v1) = value.strip_prefix("urn:btih:") {
+                        let bytes = match v1.len() {
+                            40 => hex::decode(v1).ok(),
+                            32 => hash::base32_decode(v1),
+                            _ => None,
+                        };
+                        let bytes = bytes.ok_or_else(|| format!("Invalid v1 info-hash: {v1}"))?;
+                        link.v1 = Some(hex::encode(bytes));
+                    } else if let Some(v2) = value.strip_prefix("urn:btmh:") {
+                        let digest = v2
+                            .strip_prefix("1220")
+                            .filter(|digest| digest.len() == 64 && hex::decode(digest).is_ok())
+                            .ok_or_else(|| format!("Invalid v2 info-hash: {v2}"))?;
+                        link.v2 = Some(digest.to_ascii_lowercase());
+                    }
+                }
+                "dn" => link.name = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+        if link.v1.is_none() && link.v2.is_none() {
+            return Err("Magnet link has no info-hash".to_string());
+        }
+        Ok(link)
+    }
+}
+
+/// How a magnet link differs from a torrent.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub(crate) enum Mismatch {
+    /// The magnet link points at different content.
+    Hash { magnet: String, torrent: String },
+    /// The content is the same, but the magnet link names it differently.
+    Name { magnet: String, torrent: String },
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct MagnetMatch {
+    pub hash_matches: bool,
+    /// `None` when the magnet link has no `dn`.
+    pub name_matches: Option<bool>,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl MagnetMatch {
+    /// Compares a magnet link against the torrent, by every info-hash the link has.
+    pub fn new(link: &MagnetLink, torrent: &Torrent, data_raw: &[u8]) -> Self {
+        let mut mismatches = Vec::new();
+        let v1 = hash::info_hash_v1(data_raw).map(hex::encode);
+        let v2 = hash::info_hash_v2(data_raw).map(hex::encode);
+        for (magnet, torrent) in [(&link.v1, v1), (&link.v2, v2)] {
+            if let (Some(magnet), Some(torrent)) = (magnet, torrent) {
+                if *magnet != torrent {
+                    mismatches.push(Mismatch::Hash {
+                        magnet: magnet.clone(),
+                        torrent,
+                    });
+                }
+            }
+        }
+        let hash_matches = mismatches.is_empty();
+
+        let name_matches = link.name.as_ref().map(|name| *name == torrent.info.name);
+        if name_matches == Some(false) {
+            mismatches.push(Mismatch::Name {
+                magnet: link.name.clone().unwrap_or_default(),
+                torrent: torrent.info.name.clone(),
+            });
+        }
+
+        MagnetMatch {
+            hash_matches,
+            name_matches,
+            mismatches,
+        }
+    }
+}
@@ -1,65 +1,1525 @@
-#![feature(try_blocks)]
+#![feature(ip, try_blocks)]
 
+mod archive;
+mod auth;
+mod bencode;
+mod cli;
+mod client;
+mod config;
+mod crossseed;
+mod csv;
+mod dates;
+mod decompress;
+mod error;
+mod extra;
+mod fetch;
+mod format;
+mod hash;
+mod idempotency;
+mod inspection;
+mod known;
+mod layers;
+mod layout;
+mod links;
+mod magnet;
+mod normalize;
+mod overlap;
+mod pieces;
+mod quota;
+mod reference;
+mod resume;
+mod sanitize;
+mod scan;
+mod store;
+mod timing;
 mod torrent;
+mod trackers;
+mod validate;
+mod verify;
+mod ws;
 
+use crate::config::{Config, FEATURES};
+use crate::error::{AppError, ErrorCode, ErrorResponse};
+use crate::fetch::{FetchError, FetchOptions, Fetcher};
+use crate::format::Format;
+use crate::hash::{HashOnly, HashSelection, InfoHashes};
+use crate::idempotency::Idempotency;
+use crate::inspection::{InspectOptions, Inspection, Mode, Summary};
+use crate::known::KnownHashes;
+use crate::layers::{Page, PieceLayers};
+use crate::layout::{Layout, LayoutOptions};
+use crate::magnet::{MagnetLink, MagnetMatch};
+use crate::overlap::Overlap;
+use crate::pieces::{PieceDisplay, PieceEncoding, PieceFormat, PieceHashes};
+use crate::quota::Quota;
+use crate::reference::Reference;
+use crate::resume::{Resume, ResumeSummary};
+use crate::store::{Audit, Listing, Mismatch, SaveOptions, Search, Store, StoredTorrent};
+use crate::timing::Timings;
 use crate::torrent::Torrent;
-use axum::extract::Multipart;
-use axum::response::{Html, IntoResponse};
-use axum::routing::{get, post};
-use axum::{Json, Router};
-use serde::Serialize;
-use std::io::Write;
+use crate::trackers::TrackerList;
+use crate::validate::{ValidateOptions, Verdict, Warning};
+use axum::body::{Body, Bytes, StreamBody};
+use axum::extract::{ConnectInfo, Extension, Multipart, Path, Query};
+use axum::http::header::{
+    HeaderName, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH, RETRY_AFTER,
+    VARY, WARNING,
+};
+use axum::http::{HeaderMap, HeaderValue, Method, Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{delete, get, post, put, MethodRouter};
+use axum::Router;
+use futures_util::StreamExt;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::io;
 use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio_util::io::ReaderStream;
+use tower::ServiceBuilder;
+use tower_http::compression::predicate::{DefaultPredicate, NotForContentType, Predicate};
+use tower_http::compression::CompressionLayer;
+use tower_http::request_id::{
+    MakeRequestId, MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer,
+};
+use tower_http::trace::TraceLayer;
 
-#[derive(Serialize)]
-#[serde(rename_all = "lowercase", tag = "type", content = "data")]
-enum TorrentResponse {
-    Success(Torrent),
-    Fail(String),
+/// The envelope of every response: `{"type": "success", "data": …}`, or an [`ErrorResponse`].
+enum TorrentResponse<T = Inspection> {
+    Success(T),
+    Fail(AppError),
+}
+
+impl<T: Serialize> Serialize for TorrentResponse<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            TorrentResponse::Success(data) => {
+                let mut response = serializer.serialize_struct("TorrentResponse", 2)?;
+                response.serialize_field("type", "success")?;
+                response.serialize_field("data", data)?;
+                response.end()
+            }
+            TorrentResponse::Fail(error) => ErrorResponse(error).serialize(serializer),
+        }
+    }
 }
 
 async fn index() -> Html<&'static str> {
     Html(include_str!("../index.html"))
 }
 
-async fn torrent(mut body: Multipart) -> impl IntoResponse {
-    let torrent: Option<Torrent> = try {
-        loop {
-            if let Some(field) = body.next_field().await.ok()? {
-                let name = field.name()?;
-                if name == "file" {
-                    let data_raw = field.bytes().await.ok()?;
-                    let torrent: Torrent = serde_bencode::from_bytes(data_raw.as_ref()).ok()?;
+/// Which build of the service is running.
+#[derive(Serialize)]
+struct About {
+    version: &'static str,
+    git_sha: &'static str,
+    rustc_version: &'static str,
+    /// Seconds since the Unix epoch.
+    build_timestamp: u64,
+}
+
+async fn about(format: Format) -> Response {
+    format.respond(About {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("GIT_SHA"),
+        rustc_version: env!("RUSTC_VERSION"),
+        build_timestamp: env!("BUILD_TIMESTAMP").parse().unwrap_or_default(),
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn torrent(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(store): Extension<Store>,
+    Extension(quota): Extension<Arc<Quota>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(mut options): Query<InspectOptions>,
+    Query(save): Query<SaveOptions>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let size = fields.iter().map(|(_, data)| data.len() as u64).sum();
+    if !quota.charge(addr.ip(), size) {
+        return Err(quota_exceeded());
+    }
+
+    // Relative dates would make responses depend on when they were made.
+    options.now = (!config.deterministic).then(dates::now);
+    let mut timings = Timings::new(!config.deterministic);
+    // Hash lookups neither parse nor save the torrent.
+    if options.mode == Mode::Hash {
+        let hashes: Result<HashOnly, AppError> = try {
+            let data_raw = &torrents(&fields, &config)?.remove(0);
+            let hashes = timings.time("hash", || HashOnly::scan(data_raw, options.hash));
+            hashes.ok_or_else(|| {
+                if data_raw.starts_with(b"d") {
+                    AppError::new(
+                        ErrorCode::InvalidMetainfo,
+                        "Failed to locate info dictionary",
+                    )
+                } else {
+                    AppError::new(ErrorCode::NotBencode, "Upload is not a bencoded dictionary")
+                }
+            })?
+        };
+        return Ok(timings.apply(match hashes {
+            Ok(hashes) => format.respond(TorrentResponse::Success(hashes)),
+            Err(error) => error.into_response(),
+        }));
+    }
 
-                    // save file on success
-                    let ref torrent_name = torrent.info.name;
-                    let name = torrent_name.replace("/", "／");
-                    let mut file = std::fs::File::create(format!("/tmp/{name}.torrent")).ok()?;
-                    file.write_all(data_raw.as_ref()).ok()?;
-                    drop(file);
+    let inspection: Result<Inspection, AppError> = try {
+        let data_raw = &torrents(&fields, &config)?.remove(0);
+        let torrent = timings.time("decode", || parse_torrent(data_raw, &config))?;
 
-                    break Some(torrent);
+        // save file on success
+        let info_hash = Store::key(data_raw, &torrent.info).ok_or_else(|| {
+            AppError::new(
+                ErrorCode::InvalidMetainfo,
+                "Failed to locate info dictionary",
+            )
+        })?;
+        timings
+            .time("save", || store.save(&info_hash, data_raw))
+            .map_err(|_| AppError::new(ErrorCode::StorageUnavailable, "Failed to save torrent"))?;
+        let save_as = fields
+            .iter()
+            .find(|(name, _)| name == "save_as")
+            .map(|(_, save_as)| String::from_utf8_lossy(save_as).into_owned())
+            .or(save.save_as);
+        if let Some(save_as) = save_as {
+            store.save_as(&info_hash, &save_as).map_err(|e| {
+                if e.kind() == io::ErrorKind::PermissionDenied {
+                    AppError::new(ErrorCode::SafeMode, "Safe mode does not allow save_as")
+                } else {
+                    AppError::new(ErrorCode::StorageUnavailable, "Failed to save torrent")
                 }
-            } else {
-                break None;
+            })?;
+        }
+        Inspection::timed(torrent, data_raw, &options, &mut timings)
+    };
+    // Failures are timed too, so the phases are known up to where it failed.
+    Ok(timings.apply(match inspection {
+        Ok(inspection) => format.respond(TorrentResponse::Success(inspection)),
+        Err(error) => error.into_response(),
+    }))
+}
+
+/// Downloads the torrent at `?url=` and inspects it like an upload, without saving it.
+///
+/// Fetches from the same host are spaced out by `FETCH_SPACING_MS`, and a host answering 429 or
+/// 503 with `Retry-After` is left alone for as long as it asks. A fetch that would have to wait
+/// too long for its turn is answered with 503 and a `Retry-After` of its own. A URL, or a redirect,
+/// to an address that is not public is refused with 400.
+#[allow(clippy::too_many_arguments)]
+async fn fetch_torrent(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(fetcher): Extension<Arc<Fetcher>>,
+    Extension(quota): Extension<Arc<Quota>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(fetch): Query<FetchOptions>,
+    Query(mut options): Query<InspectOptions>,
+) -> Result<Response, AppError> {
+    let body = match fetcher.fetch(&fetch.url, config.max_upload_bytes).await {
+        Err(error @ FetchError::Throttled { retry_after, .. }) => {
+            let retry_after = retry_after.as_secs().max(1).to_string();
+            return Ok(([(RETRY_AFTER, retry_after)], AppError::from(error)).into_response());
+        }
+        body => body?,
+    };
+    if !quota.charge(addr.ip(), body.len() as u64) {
+        return Err(quota_exceeded());
+    }
+    let data_raw = match decompress::is_gzip(&body) {
+        true => decompress::gunzip(&body, config.max_decompressed_bytes)?,
+        false => body,
+    };
+    options.now = (!config.deterministic).then(dates::now);
+    let torrent = parse_torrent(&data_raw, &config)?;
+    let inspection = Inspection::new(torrent, &data_raw, &options);
+    Ok(format.respond(TorrentResponse::Success(inspection)))
+}
+
+/// Saves the raw torrent in the body under the info-hash in the path, replacing any earlier copy.
+///
+/// The path may name either info-hash of a hybrid torrent, and must match the body: a mismatch is
+/// rejected with 400. Answers 201 when the torrent is new, and 200 when it was saved before.
+#[allow(clippy::too_many_arguments)]
+async fn put_torrent(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(store): Extension<Store>,
+    Extension(quota): Extension<Arc<Quota>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(info_hash): Path<String>,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    if !quota.charge(addr.ip(), body.len() as u64) {
+        return Err(quota_exceeded());
+    }
+    if store.path(&info_hash).is_none() {
+        return Err(AppError::new(
+            ErrorCode::InvalidRequest,
+            format!("{info_hash} is not a hex info-hash"),
+        ));
+    }
+    let data_raw = match decompress::is_gzip(&body) {
+        true => decompress::gunzip(&body, config.max_decompressed_bytes)?,
+        false => body.to_vec(),
+    };
+    let torrent = parse_torrent(&data_raw, &config)?;
+    let hashes = InfoHashes::compute(&data_raw, &torrent.info, HashSelection::Both);
+    let requested = info_hash.to_ascii_lowercase();
+    if hashes.v1.as_ref() != Some(&requested) && hashes.v2.as_ref() != Some(&requested) {
+        return Err(AppError::new(
+            ErrorCode::TorrentMismatch,
+            format!("The uploaded torrent's info-hash is not {info_hash}"),
+        ));
+    }
+    let key = Store::key(&data_raw, &torrent.info).ok_or_else(|| {
+        AppError::new(
+            ErrorCode::InvalidMetainfo,
+            "Failed to locate info dictionary",
+        )
+    })?;
+    let existed = store.path(&key).is_some_and(|path| path.exists());
+    store
+        .save(&key, &data_raw)
+        .map_err(|_| AppError::new(ErrorCode::StorageUnavailable, "Failed to save torrent"))?;
+    let status = if existed {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    Ok(format.respond_with(status, TorrentResponse::Success(hashes)))
+}
+
+/// Removes a saved torrent, answering 204, or 404 if there was none.
+///
+/// A deterministic store is never written to, so deleting from it is refused with 403.
+///
+/// Always behind [`auth::require_token`], so it is refused altogether without `ADMIN_TOKEN`.
+async fn delete_torrent(
+    Extension(store): Extension<Store>,
+    Path(info_hash): Path<String>,
+) -> Result<StatusCode, AppError> {
+    match store.delete(&info_hash).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(not_saved(&info_hash)),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Err(AppError::new(
+            ErrorCode::ReadOnly,
+            "Deterministic mode does not allow deleting saved torrents",
+        )),
+        Err(e) if e.kind() == io::ErrorKind::InvalidInput => Err(AppError::new(
+            ErrorCode::InvalidRequest,
+            format!("{info_hash} is not a hex info-hash"),
+        )),
+        Err(_) => Err(AppError::new(
+            ErrorCode::StorageUnavailable,
+            "Failed to delete torrent",
+        )),
+    }
+}
+
+/// Downloads a saved torrent, by its info-hash or any unambiguous prefix of it.
+async fn download(
+    Extension(store): Extension<Store>,
+    Path(info_hash): Path<String>,
+) -> Result<Response, AppError> {
+    let info_hash = if store.path(&info_hash).is_some() {
+        info_hash
+    } else {
+        match store.find(&info_hash).await {
+            Ok(candidates) if candidates.len() > 1 => {
+                return Err(AppError::new(
+                    ErrorCode::AmbiguousPrefix,
+                    format!(
+                        "{info_hash} is ambiguous, it could be any of {}",
+                        candidates.join(", ")
+                    ),
+                ))
+            }
+            Ok(mut candidates) if candidates.len() == 1 => candidates.remove(0),
+            _ => return Err(not_saved(&info_hash)),
+        }
+    };
+    // Streamed in chunks, as torrents with huge piece lists can be tens of MiB.
+    let (file, size) = store
+        .open(&info_hash)
+        .await
+        .map_err(|_| not_saved(&info_hash))?;
+    Ok((
+        [
+            (
+                CONTENT_TYPE,
+                HeaderValue::from_static("application/x-bittorrent"),
+            ),
+            (CONTENT_LENGTH, HeaderValue::from(size)),
+        ],
+        StreamBody::new(ReaderStream::with_capacity(file, 64 * 1024)),
+    )
+        .into_response())
+}
+
+/// Summarizes a saved torrent.
+///
+/// A saved torrent can be replaced under the same info-hash with other trackers or another
+/// creation date, or deleted, so the summary is only cached for a minute. Its `ETag` is a hash
+/// of the saved file and the format, which `If-None-Match` can be checked against instead of
+/// downloading the summary again.
+async fn summary(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(store): Extension<Store>,
+    Path(info_hash): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let data_raw = store
+        .read(&info_hash)
+        .await
+        .map_err(|_| not_saved(&info_hash))?;
+    let etag = format!(
+        "\"{}-{}\"",
+        hex::encode(Sha256::digest(&data_raw)),
+        match format {
+            Format::Json => "json",
+            Format::MessagePack => "msgpack",
+            Format::Cbor => "cbor",
+        }
+    );
+    let not_modified = headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+    let mut response = if not_modified {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        let torrent = parse_saved(&data_raw, &config, &info_hash)?;
+        format.respond(TorrentResponse::Success(Summary::new(torrent)))
+    };
+    let headers = response.headers_mut();
+    headers.insert(
+        CACHE_CONTROL,
+        HeaderValue::from_static("public, max-age=60"),
+    );
+    headers.insert(VARY, HeaderValue::from_static("accept"));
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        headers.insert(ETAG, etag);
+    }
+    Ok(response)
+}
+
+/// Reads and parses a saved torrent, which is as good as not saved if it does not parse.
+///
+/// One with more than `MAX_FILES` files is refused like an upload would be.
+async fn saved_torrent(
+    store: &Store,
+    config: &Config,
+    info_hash: &str,
+) -> Result<Torrent, AppError> {
+    let data_raw = store
+        .read(info_hash)
+        .await
+        .map_err(|_| not_saved(info_hash))?;
+    parse_saved(&data_raw, config, info_hash)
+}
+
+/// Parses a saved torrent read by [`saved_torrent`].
+fn parse_saved(data_raw: &[u8], config: &Config, info_hash: &str) -> Result<Torrent, AppError> {
+    parse_torrent(data_raw, config).map_err(|e| match e.code {
+        ErrorCode::TooManyFiles => e,
+        _ => not_saved(info_hash),
+    })
+}
+
+/// Lists the files of a saved torrent as CSV.
+async fn files_csv(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(store): Extension<Store>,
+    Path(info_hash): Path<String>,
+) -> Result<Response, AppError> {
+    let torrent = saved_torrent(&store, &config, &info_hash).await?;
+    Ok((
+        [(CONTENT_TYPE, "text/csv; charset=utf-8")],
+        csv::files_csv(&torrent.info),
+    )
+        .into_response())
+}
+
+/// Lists every distinct tracker of a saved torrent, one URL per line, for tools adding trackers.
+async fn trackers_txt(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(store): Extension<Store>,
+    Path(info_hash): Path<String>,
+) -> Result<Response, AppError> {
+    let torrent = saved_torrent(&store, &config, &info_hash).await?;
+    let mut trackers = String::new();
+    for tracker in torrent.effective_trackers() {
+        trackers.push_str(&tracker);
+        trackers.push('\n');
+    }
+    Ok(([(CONTENT_TYPE, "text/plain; charset=utf-8")], trackers).into_response())
+}
+
+/// Collects every field of the upload with its name.
+async fn fields(body: &mut Multipart) -> Result<Vec<(String, Bytes)>, AppError> {
+    let unreadable = |_| AppError::new(ErrorCode::UploadUnreadable, "Failed to read upload");
+    let mut fields = Vec::new();
+    while let Some(field) = body.next_field().await.map_err(unreadable)? {
+        let name = field.name().unwrap_or_default().to_string();
+        fields.push((name, field.bytes().await.map_err(unreadable)?));
+    }
+    Ok(fields)
+}
+
+/// The failure for an info-hash that no saved torrent has.
+fn not_saved(info_hash: &str) -> AppError {
+    AppError::new(
+        ErrorCode::NotFound,
+        format!("No torrent saved as {info_hash}"),
+    )
+}
+
+/// The failure for a store whose directories could not be listed.
+pub(crate) fn unreadable_store() -> AppError {
+    AppError::new(
+        ErrorCode::StorageUnavailable,
+        "Failed to read saved torrents",
+    )
+}
+
+/// The failure for an upload over the client IP's daily quota.
+fn quota_exceeded() -> AppError {
+    AppError::new(ErrorCode::QuotaExceeded, "Daily upload quota exceeded")
+}
+
+/// Picks the uploaded torrents out of the fields, by the field names accepted in the config.
+/// Gzip compressed torrents are decompressed.
+///
+/// When there are none, the error lists the fields that were present, to point out a misnamed one.
+fn torrents(fields: &[(String, Bytes)], config: &Config) -> Result<Vec<Bytes>, AppError> {
+    let mut torrents = Vec::new();
+    for (_, data) in fields
+        .iter()
+        .filter(|(name, _)| config.upload_fields.contains(name))
+    {
+        if decompress::is_gzip(data) {
+            let data = decompress::gunzip(data, config.max_decompressed_bytes)?;
+            torrents.push(Bytes::from(data));
+        } else {
+            torrents.push(data.clone());
+        }
+    }
+    if torrents.is_empty() {
+        let present: Vec<_> = fields.iter().map(|(name, _)| name.as_str()).collect();
+        return Err(AppError::new(
+            ErrorCode::NoTorrentUploaded,
+            format!(
+                "No torrent uploaded: expected a field named {}, but got fields [{}]",
+                config.upload_fields.join(" or "),
+                present.join(", ")
+            ),
+        )
+        .with_details(serde_json::json!({
+            "expected": config.upload_fields,
+            "fields": present,
+        })));
+    }
+    Ok(torrents)
+}
+
+/// Parses an uploaded torrent, telling apart why it could not be.
+///
+/// A torrent with more than `MAX_FILES` files is rejected, before its file list is even decoded
+/// when the raw bytes show as much. With `TOO_MANY_FILES=truncate`, only that many of its v1
+/// **files** are decoded instead, and the rest are skipped.
+fn parse_torrent(data_raw: &[u8], config: &Config) -> Result<Torrent, AppError> {
+    let too_many = || {
+        AppError::new(
+            ErrorCode::TooManyFiles,
+            format!("The torrent has more than {} files", config.max_files),
+        )
+        .with_details(serde_json::json!({ "limit": config.max_files }))
+    };
+    let declared = bencode::info_slice(data_raw)
+        .and_then(|info| bencode::dict_get(info, b"files"))
+        .and_then(bencode::list_len);
+    if declared.is_some_and(|files| files > config.max_files) && !config.truncate_files {
+        return Err(too_many());
+    }
+
+    let (torrent, skipped) = torrent::with_file_cap(config.max_files, || {
+        serde_bencode::from_bytes::<Torrent>(data_raw)
+    });
+    let mut torrent = torrent.map_err(|e| AppError::parse(data_raw, e))?;
+    torrent.info.files_omitted = skipped;
+    if (skipped > 0 && !config.truncate_files) || torrent.info.file_count() > config.max_files {
+        return Err(too_many());
+    }
+    Ok(torrent)
+}
+
+/// Compares the files of two uploaded torrents by path and length, see [`Overlap`].
+async fn overlap(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let files = torrents(&fields, &config)?;
+    if files.len() != 2 {
+        return Err(AppError::new(
+            ErrorCode::InvalidRequest,
+            "Exactly two torrents are required",
+        ));
+    }
+    let first = parse_torrent(&files[0], &config)?;
+    let second = parse_torrent(&files[1], &config)?;
+    let overlap = Overlap::new(&first.info, &second.info);
+    Ok(format.respond(TorrentResponse::Success(overlap)))
+}
+
+async fn merge_trackers(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let files = torrents(&fields, &config)?;
+    if files.len() != 2 {
+        return Err(AppError::new(
+            ErrorCode::InvalidRequest,
+            "Exactly two torrents are required",
+        ));
+    }
+
+    let mut torrents = Vec::new();
+    let mut hashes = Vec::new();
+    for data_raw in &files {
+        let torrent = parse_torrent(data_raw, &config)?;
+        torrents.push(torrent);
+        hashes.push(hash::info_hash_v1(data_raw).ok_or_else(|| {
+            AppError::new(
+                ErrorCode::InvalidMetainfo,
+                "Failed to locate info dictionary",
+            )
+        })?);
+    }
+    if hashes[0] != hashes[1] {
+        return Err(AppError::new(
+            ErrorCode::TorrentMismatch,
+            format!(
+                "Info-hashes differ: {} and {}",
+                hex::encode(hashes[0]),
+                hex::encode(hashes[1])
+            ),
+        ));
+    }
+
+    let merged = TrackerList::merge(&torrents);
+    Ok(format.respond(TorrentResponse::Success(merged)))
+}
+
+async fn validate(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    Query(options): Query<ValidateOptions>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let data_raw = &torrents(&fields, &config)?.remove(0);
+    let verdict = match parse_torrent(data_raw, &config) {
+        Ok(torrent) => {
+            let mut problems = validate::validate(&torrent);
+            problems.extend(validate::check_trailing(data_raw));
+            if let Some(allowed) = &config.allowed_trackers {
+                problems.extend(validate::check_allowed_trackers(&torrent, allowed));
             }
-        }?
+            if options.strict {
+                problems.extend(validate::check_integers(data_raw));
+            }
+            Verdict::new(problems)
+        }
+        // A torrent too large to parse is refused, not reported as broken.
+        Err(e) if e.code == ErrorCode::TooManyFiles => return Err(e),
+        Err(e) => Verdict::new(vec![Warning::Malformed { message: e.message }]),
     };
-    match torrent {
-        Some(torrent) => Json(TorrentResponse::Success(torrent)),
-        None => Json(TorrentResponse::Fail("Failed to parse torrent".to_string())),
+    Ok(format.respond(TorrentResponse::Success(verdict)))
+}
+
+/// Previews the files a download of the torrent would create on the chosen system.
+async fn layout(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    Query(options): Query<LayoutOptions>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let data_raw = &torrents(&fields, &config)?.remove(0);
+    let torrent = parse_torrent(data_raw, &config)?;
+    let layout = Layout::new(&torrent.info, options.os);
+    Ok(format.respond(TorrentResponse::Success(layout)))
+}
+
+/// Checks whether the torrent's info-hash is in the list of known good ones.
+async fn known(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(known): Extension<Arc<KnownHashes>>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let data_raw = &torrents(&fields, &config)?.remove(0);
+    let torrent = parse_torrent(data_raw, &config)?;
+    let found = known.check(InfoHashes::compute(
+        data_raw,
+        &torrent.info,
+        HashSelection::Both,
+    ));
+    Ok(format.respond(TorrentResponse::Success(found)))
+}
+
+#[derive(Serialize)]
+struct Reloaded {
+    count: usize,
+}
+
+/// Reads `KNOWN_HASHES_FILE` again, keeping the old list if that fails.
+async fn reload_known(
+    format: Format,
+    Extension(known): Extension<Arc<KnownHashes>>,
+) -> Result<Response, AppError> {
+    let count = known.reload().map_err(|_| {
+        AppError::new(
+            ErrorCode::StorageUnavailable,
+            "Failed to read the known hashes",
+        )
+    })?;
+    Ok(format.respond(TorrentResponse::Success(Reloaded { count })))
+}
+
+/// Lists the v1 piece hashes of the uploaded torrent, up to `PIECE_DISPLAY_LIMIT` unless `?all=true`.
+///
+/// With `?format=binary`, the hashes are sent as they are in **pieces**, with their total count
+/// and whether they were cut short in the `piece-count` and `pieces-truncated` headers.
+async fn pieces(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    Query(display): Query<PieceDisplay>,
+    Query(encoding): Query<PieceFormat>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let data_raw = &torrents(&fields, &config)?.remove(0);
+    let torrent = parse_torrent(data_raw, &config)?;
+    if !torrent.info.has_v1() {
+        return Err(AppError::new(
+            ErrorCode::InvalidRequest,
+            "Not a v1 torrent, so it has no piece hashes",
+        ));
+    }
+    let limit = display.limit(config.piece_display_limit);
+    if encoding.format == PieceEncoding::Binary {
+        let total = torrent.info.piece_hashes().count();
+        let listed = limit.map_or(total, |limit| limit.min(total));
+        let mut pieces = torrent.info.pieces;
+        pieces.truncate(listed * 20);
+        return Ok((
+            [
+                (CONTENT_TYPE, "application/octet-stream".to_string()),
+                (PIECE_COUNT, total.to_string()),
+                (PIECES_TRUNCATED, (listed < total).to_string()),
+            ],
+            pieces,
+        )
+            .into_response());
+    }
+    Ok(format.respond(TorrentResponse::Success(PieceHashes::new(
+        &torrent.info,
+        limit,
+    ))))
+}
+
+/// The number of piece hashes in the torrent, whether or not all of them were sent.
+const PIECE_COUNT: HeaderName = HeaderName::from_static("piece-count");
+/// Whether the piece hashes sent stop short of `piece-count`.
+const PIECES_TRUNCATED: HeaderName = HeaderName::from_static("pieces-truncated");
+
+/// Lists the pieces root and piece layer hashes of each file of a v2 torrent, a page at a time.
+async fn piece_layers(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    Query(page): Query<Page>,
+    Query(display): Query<PieceDisplay>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let data_raw = &torrents(&fields, &config)?.remove(0);
+    let torrent = parse_torrent(data_raw, &config)?;
+    let limit = display.limit(config.piece_display_limit);
+    let layers = PieceLayers::new(&torrent, &page, limit).ok_or_else(|| {
+        AppError::new(
+            ErrorCode::InvalidRequest,
+            "Not a v2 torrent, so it has no piece layers",
+        )
+    })?;
+    Ok(format.respond(TorrentResponse::Success(layers)))
+}
+
+/// Rewrites the uploaded torrent into a canonical form, see [`normalize::normalize`].
+async fn normalize(
+    Extension(config): Extension<Arc<Config>>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let data_raw = &torrents(&fields, &config)?.remove(0);
+    let torrent = parse_torrent(data_raw, &config)?;
+    let normalized = normalize::normalize(data_raw, &torrent).ok_or_else(|| {
+        AppError::new(
+            ErrorCode::NormalizationFailed,
+            "Normalizing would change the info-hash",
+        )
+    })?;
+    Ok(([(CONTENT_TYPE, "application/x-bittorrent")], normalized).into_response())
+}
+
+/// Removes every tracker from the torrent, for downloading it over DHT only.
+///
+/// Private torrents are returned stripped as well, but with a `Warning` header: clients do not
+/// use DHT for them, and clearing **private** would change the info-hash.
+async fn strip_trackers(
+    Extension(config): Extension<Arc<Config>>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let data_raw = &torrents(&fields, &config)?.remove(0);
+    let torrent = parse_torrent(data_raw, &config)?;
+    let stripped = normalize::strip_trackers(data_raw).ok_or_else(|| {
+        AppError::new(
+            ErrorCode::NormalizationFailed,
+            "Stripping trackers would change the info-hash",
+        )
+    })?;
+    let mut response = ([(CONTENT_TYPE, "application/x-bittorrent")], stripped).into_response();
+    if torrent.info.private == Some(1) {
+        response.headers_mut().insert(
+            WARNING,
+            HeaderValue::from_static(
+                "199 - \"Private torrent: clients will not use DHT, so it has no way to find peers\"",
+            ),
+        );
     }
+    Ok(response)
+}
+
+#[derive(Serialize)]
+struct Magnet {
+    magnet: String,
+}
+
+/// Builds a magnet link, optionally selecting files by the `select` fields,
+/// each holding either a file index or a path relative to the torrent root.
+async fn magnet(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let data_raw = &torrents(&fields, &config)?.remove(0);
+    let torrent = parse_torrent(data_raw, &config)?;
+
+    let mut select_only = Vec::new();
+    for (_, selection) in fields.iter().filter(|(name, _)| name == "select") {
+        let selection = String::from_utf8_lossy(selection);
+        select_only.push(
+            magnet::resolve_selection(&torrent, &selection)
+                .map_err(|message| AppError::new(ErrorCode::InvalidRequest, message))?,
+        );
+    }
+
+    let hashes = InfoHashes::compute(data_raw, &torrent.info, HashSelection::Both);
+    let magnet = Magnet {
+        magnet: magnet::magnet(&torrent, &hashes, &select_only),
+    };
+    Ok(format.respond(TorrentResponse::Success(magnet)))
+}
+
+#[derive(Serialize)]
+struct EncodedReference {
+    reference: String,
+}
+
+/// Encodes the essentials of the uploaded torrent into a compact reference.
+async fn encode_reference(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let data_raw = &torrents(&fields, &config)?.remove(0);
+    let torrent = parse_torrent(data_raw, &config)?;
+    let hashes = InfoHashes::compute(data_raw, &torrent.info, HashSelection::Both);
+    let reference = Reference {
+        v1: hashes.v1,
+        v2: hashes.v2,
+        trackers: torrent.tiers().into_iter().flatten().collect(),
+        name: torrent.info.name,
+    };
+    let reference = EncodedReference {
+        reference: reference.encode(),
+    };
+    Ok(format.respond(TorrentResponse::Success(reference)))
+}
+
+/// Expands a reference made by [`encode_reference`] back.
+async fn decode_reference(
+    format: Format,
+    Path(reference): Path<String>,
+) -> Result<Response, AppError> {
+    let reference = Reference::decode(&reference)
+        .map_err(|message| AppError::new(ErrorCode::InvalidRequest, message))?;
+    Ok(format.respond(TorrentResponse::Success(reference)))
+}
+
+/// Checks whether the link in the `magnet` field points at the uploaded torrent.
+async fn match_magnet(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let data_raw = &torrents(&fields, &config)?.remove(0);
+    let torrent = parse_torrent(data_raw, &config)?;
+    let magnet = fields
+        .iter()
+        .find(|(name, _)| name == "magnet")
+        .map(|(_, magnet)| String::from_utf8_lossy(magnet))
+        .ok_or_else(|| AppError::new(ErrorCode::InvalidRequest, "No magnet link given"))?;
+    let link = MagnetLink::parse(&magnet)
+        .map_err(|message| AppError::new(ErrorCode::InvalidRequest, message))?;
+    let matched = MagnetMatch::new(&link, &torrent, data_raw);
+    Ok(format.respond(TorrentResponse::Success(matched)))
+}
+
+/// Parses libtorrent style `.fastresume` data.
+async fn resume(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let data_raw = &torrents(&fields, &config)?.remove(0);
+    let resume: Resume = serde_bencode::from_bytes(data_raw.as_ref())
+        .map_err(|_| AppError::new(ErrorCode::InvalidResumeData, "Failed to parse resume data"))?;
+    let summary = ResumeSummary::from(resume);
+    Ok(format.respond(TorrentResponse::Success(summary)))
+}
+
+/// The inspection of one torrent in an archive.
+#[derive(Serialize)]
+struct ArchiveEntry {
+    path: String,
+    result: TorrentResponse,
+}
+
+/// Inspects every `.torrent` file in a zip or tar archive, which may also be gzip compressed.
+async fn inspect_archive(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(quota): Extension<Arc<Quota>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(mut options): Query<InspectOptions>,
+    mut body: Multipart,
+) -> Result<Response, AppError> {
+    let fields = fields(&mut body).await?;
+    let size = fields.iter().map(|(_, data)| data.len() as u64).sum();
+    if !quota.charge(addr.ip(), size) {
+        return Err(quota_exceeded());
+    }
+
+    options.now = (!config.deterministic).then(dates::now);
+    let data = &torrents(&fields, &config)?.remove(0);
+    let entries = archive::torrents(
+        data,
+        config.max_archive_entries,
+        config.max_decompressed_bytes,
+    )?;
+    // Parsed on the blocking thread pool, at most `parse_workers` at once and in order.
+    let entries: Vec<ArchiveEntry> = futures_util::stream::iter(entries)
+        .map(|(path, data_raw)| {
+            let config = config.clone();
+            async move {
+                let result =
+                    tokio::task::spawn_blocking(move || match parse_torrent(&data_raw, &config) {
+                        Ok(torrent) => {
+                            TorrentResponse::Success(Inspection::new(torrent, &data_raw, &options))
+                        }
+                        Err(error) => TorrentResponse::Fail(error),
+                    })
+                    .await
+                    .unwrap_or_else(|_| {
+                        TorrentResponse::Fail(AppError::new(
+                            ErrorCode::InvalidMetainfo,
+                            format!("Failed to parse {path}"),
+                        ))
+                    });
+                ArchiveEntry { path, result }
+            }
+        })
+        .buffered(config.parse_workers)
+        .collect()
+        .await;
+    Ok(format.respond(TorrentResponse::Success(entries)))
+}
+
+/// Lists the saved torrents a page at a time, sorted by file name, optionally searching by name.
+///
+/// Every torrent is loaded to know the total, as the store keeps no index.
+async fn list_torrents(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(store): Extension<Store>,
+    Query(search): Query<Search>,
+    Query(page): Query<Page>,
+) -> Result<Response, AppError> {
+    let paths = store.paths().await.map_err(|_| unreadable_store())?;
+    let q = search.q.map(|q| q.to_lowercase());
+    let mut torrents: Vec<StoredTorrent> = paths
+        .map(|path| {
+            let config = config.clone();
+            async move { Store::load(&path, config).await }
+        })
+        .buffer_unordered(config.parse_workers)
+        .filter_map(|torrent| {
+            let matches = torrent.filter(|torrent| {
+                q.as_ref()
+                    .is_none_or(|q| torrent.name.to_lowercase().contains(q))
+            });
+            async move { matches }
+        })
+        .collect()
+        .await;
+    torrents.sort_by(|a, b| a.file.cmp(&b.file));
+    let total = torrents.len();
+    let torrents = torrents
+        .into_iter()
+        .skip(page.offset)
+        .take(page.limit.min(MAX_LISTED))
+        .collect();
+    Ok(format.respond(TorrentResponse::Success(Listing {
+        total,
+        offset: page.offset,
+        torrents,
+    })))
+}
+
+/// The most saved torrents listed at once, whatever `limit` asks for.
+const MAX_LISTED: usize = 1000;
+
+/// Rehashes every saved torrent and lists those whose file name is not their info-hash, for cleanup.
+async fn audit_torrents(
+    format: Format,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(store): Extension<Store>,
+) -> Result<Response, AppError> {
+    let paths = store.paths().await.map_err(|_| unreadable_store())?;
+    let mut checked = 0;
+    let mut mismatches: Vec<Mismatch> = paths
+        .map(|path| {
+            checked += 1;
+            let config = config.clone();
+            async move { Store::verify(&path, config).await }
+        })
+        .buffer_unordered(config.parse_workers)
+        .filter_map(|mismatch| async move { mismatch })
+        .collect()
+        .await;
+    mismatches.sort_by(|a, b| a.file.cmp(&b.file));
+    Ok(format.respond(TorrentResponse::Success(Audit {
+        checked,
+        mismatches,
+    })))
+}
+
+/// Streams a summary of every saved torrent as newline delimited JSON, one per line.
+async fn stream_torrents(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(store): Extension<Store>,
+) -> Result<Response, AppError> {
+    let paths = store.paths().await.map_err(|_| unreadable_store())?;
+    // Up to `parse_workers` torrents are loaded at once, still streamed in order.
+    let workers = config.parse_workers;
+    let lines = paths.map(move |path| {
+        let config = config.clone();
+        async move {
+            let line = match Store::load(&path, config).await {
+                Some(torrent) => TorrentResponse::Success(torrent),
+                None => TorrentResponse::Fail(AppError::new(
+                    ErrorCode::InvalidMetainfo,
+                    format!("Failed to parse {}", path.display()),
+                )),
+            };
+            let mut line = serde_json::to_vec(&line).unwrap_or_default();
+            line.push(b'\n');
+            Ok::<_, Infallible>(Bytes::from(line))
+        }
+    });
+    let lines = lines.buffered(workers);
+    Ok((
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        StreamBody::new(lines),
+    )
+        .into_response())
+}
+
+/// Refuses every `PUT`, `PATCH` and `DELETE` in safe mode, as they exist to change what is saved,
+/// and `/torrent/fetch`, as it reaches out to the network.
+///
+/// Writes that are only part of a request, like `save_as`, are refused by the [`Store`] instead.
+async fn safe_mode(request: Request<Body>, next: Next<Body>) -> Response {
+    let safe_mode = request
+        .extensions()
+        .get::<Arc<Config>>()
+        .is_some_and(|config| config.safe_mode);
+    if !safe_mode {
+        return next.run(request).await;
+    }
+    if matches!(
+        *request.method(),
+        Method::PUT | Method::PATCH | Method::DELETE
+    ) {
+        return AppError::new(
+            ErrorCode::SafeMode,
+            "Safe mode does not allow changing saved torrents",
+        )
+        .into_response();
+    }
+    if request.uri().path() == "/torrent/fetch" {
+        return AppError::new(
+            ErrorCode::SafeMode,
+            "Safe mode does not allow fetching torrents",
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+/// Rejects request bodies larger than the configured limit.
+///
+/// A `Content-Length` over the limit is answered with 413 before reading anything.
+/// Bodies without one, like chunked uploads, are cut off once they grow past the limit,
+/// which the handler then sees as a failed read.
+async fn limit_upload(mut request: Request<Body>, next: Next<Body>) -> Response {
+    let Some(config) = request.extensions().get::<Arc<Config>>() else {
+        return next.run(request).await;
+    };
+    let limit = config.max_upload_bytes;
+    let content_length = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok());
+    match content_length {
+        Some(length) if length > limit => {
+            return AppError::new(
+                ErrorCode::UploadTooLarge,
+                format!("Upload is larger than {limit} bytes"),
+            )
+            .with_details(serde_json::json!({ "limit": limit }))
+            .into_response();
+        }
+        Some(_) => {}
+        None => {
+            let mut remaining = limit;
+            let body = std::mem::take(request.body_mut()).map(move |chunk| {
+                let chunk = chunk?;
+                remaining = remaining.checked_sub(chunk.len() as u64).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "upload too large")
+                })?;
+                Ok::<_, Box<dyn std::error::Error + Send + Sync>>(chunk)
+            });
+            *request.body_mut() = Body::wrap_stream(body);
+        }
+    }
+    next.run(request).await
+}
+
+fn request_span(request: &Request<Body>) -> tracing::Span {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id,
+    )
+}
+
+/// A request ID generator counting up from 1, for deterministic mode.
+#[derive(Debug, Clone, Default)]
+struct MakeRequestSequence(Arc<AtomicU64>);
+
+impl MakeRequestId for MakeRequestSequence {
+    fn make_request_id<B>(&mut self, _: &Request<B>) -> Option<RequestId> {
+        let id = self.0.fetch_add(1, Ordering::Relaxed) + 1;
+        Some(RequestId::new(HeaderValue::from(id)))
+    }
+}
+
+/// Makes random request IDs, or sequential ones in deterministic mode.
+#[derive(Clone)]
+enum MakeRequestIds {
+    Uuid(MakeRequestUuid),
+    Sequence(MakeRequestSequence),
+}
+
+impl MakeRequestId for MakeRequestIds {
+    fn make_request_id<B>(&mut self, request: &Request<B>) -> Option<RequestId> {
+        match self {
+            MakeRequestIds::Uuid(uuid) => uuid.make_request_id(request),
+            MakeRequestIds::Sequence(sequence) => sequence.make_request_id(request),
+        }
+    }
+}
+
+/// Builds the service with every enabled route and the layers around them.
+fn app(config: Config) -> Router {
+    for feature in config.enabled_features.iter().flatten() {
+        if !FEATURES.contains(&feature.as_str()) {
+            tracing::warn!("Unknown feature {feature} in ENABLED_FEATURES");
+        }
+    }
+
+    // Routes of disabled features are never registered, so they 404 like any unknown path.
+    let routes: Vec<(&str, &str, MethodRouter)> = vec![
+        ("index", "/", get(index)),
+        ("about", "/about", get(about)),
+        (
+            "inspect",
+            "/torrent",
+            post(torrent).layer(middleware::from_fn(idempotency::idempotent)),
+        ),
+        ("fetch", "/torrent/fetch", post(fetch_torrent)),
+        ("download", "/torrent/:info_hash", get(download)),
+        ("put", "/torrent/:info_hash", put(put_torrent)),
+        ("delete", "/torrent/:info_hash", delete(delete_torrent)),
+        ("summary", "/torrent/:info_hash/summary", get(summary)),
+        ("files-csv", "/torrent/:info_hash/files.csv", get(files_csv)),
+        ("verify", "/torrent/:info_hash/verify", get(verify::verify)),
+        (
+            "trackers-txt",
+            "/torrent/:info_hash/trackers.txt",
+            get(trackers_txt),
+        ),
+        (
+            "merge-trackers",
+            "/torrent/merge-trackers",
+            post(merge_trackers),
+        ),
+        ("overlap", "/torrent/overlap", post(overlap)),
+        ("normalize", "/torrent/normalize", post(normalize)),
+        (
+            "strip-trackers",
+            "/torrent/strip-trackers",
+            post(strip_trackers),
+        ),
+        ("pieces", "/torrent/pieces", post(pieces)),
+        ("piece-layers", "/torrent/piece-layers", post(piece_layers)),
+        ("layout", "/torrent/layout", post(layout)),
+        ("known-hashes", "/torrent/known", post(known)),
+        ("known-hashes", "/known-hashes/reload", post(reload_known)),
+        ("magnet", "/torrent/magnet", post(magnet)),
+        ("match-magnet", "/torrent/match-magnet", post(match_magnet)),
+        ("reference", "/torrent/reference", post(encode_reference)),
+        ("reference", "/reference/:reference", get(decode_reference)),
+        ("validate", "/validate", post(validate)),
+        ("resume", "/resume", post(resume)),
+        ("list", "/torrents", get(list_torrents)),
+        ("audit", "/torrents/audit", get(audit_torrents)),
+        ("stream", "/torrents/stream", get(stream_torrents)),
+        ("scan", "/torrents/scan", get(scan::scan)),
+        ("archive", "/torrents/archive", post(inspect_archive)),
+        ("ws", "/ws", get(ws::inspect)),
+    ];
+    let store = if config.safe_mode {
+        Store::safe(&config.store_dir)
+    } else if config.deterministic {
+        Store::read_only(&config.store_dir)
+    } else {
+        Store::new(&config.store_dir)
+    };
+    let known = KnownHashes::new(config.known_hashes_file.clone());
+    let fetcher = Fetcher::new(&config);
+    let (quota, idempotency, request_ids) = if config.deterministic {
+        (
+            Quota::frozen(config.upload_quota),
+            Idempotency::new(None),
+            MakeRequestIds::Sequence(MakeRequestSequence::default()),
+        )
+    } else {
+        let quota_file = config.upload_quota_file.clone();
+        (
+            Quota::new(
+                config.upload_quota,
+                quota_file.filter(|_| !config.safe_mode),
+            ),
+            Idempotency::new(Some(config.idempotency_ttl)),
+            MakeRequestIds::Uuid(MakeRequestUuid),
+        )
+    };
+    routes
+        .into_iter()
+        .filter(|(feature, _, _)| config.is_enabled(feature))
+        .fold(Router::new(), |app, (feature, path, route)| {
+            let route = match config.requires_token(feature) {
+                true => route.layer(middleware::from_fn(auth::require_token)),
+                false => route,
+            };
+            app.route(path, route)
+        })
+        .layer(middleware::from_fn(limit_upload))
+        .layer(middleware::from_fn(safe_mode))
+        .layer(middleware::from_fn(error::negotiate))
+        .layer(Extension(store))
+        .layer(Extension(Arc::new(quota)))
+        .layer(Extension(Arc::new(idempotency)))
+        .layer(Extension(Arc::new(known)))
+        .layer(Extension(Arc::new(fetcher)))
+        .layer(Extension(Arc::new(config)))
+        .layer(
+            // An incoming `X-Request-Id` is kept, otherwise one is generated.
+            // Either way it is added to the request span and echoed back in the response.
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(request_ids))
+                .layer(TraceLayer::new_for_http().make_span_with(request_span))
+                .layer(PropagateRequestIdLayer::x_request_id())
+                // Compressed NDJSON would sit in the encoder until enough of it piles up,
+                // so streamed responses are sent as they are, line by line. Likewise for events.
+                .layer(
+                    CompressionLayer::new().compress_when(
+                        DefaultPredicate::new()
+                            .and(NotForContentType::const_new("application/x-ndjson"))
+                            .and(NotForContentType::const_new("text/event-stream")),
+                    ),
+                ),
+        )
 }
 
 #[tokio::main]
-async fn main() {
-    let app = Router::new()
-        .route("/", get(index))
-        .route("/torrent", post(torrent));
+async fn main() -> ExitCode {
+    // `--safe` anywhere turns on safe mode, like `SAFE_MODE=1`.
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut config = Config::from_env();
+    if args.iter().any(|arg| arg == "--safe") {
+        config.safe_mode = true;
+        args.retain(|arg| arg != "--safe");
+    }
 
+    // `torrent-inspector <path>` inspects a single torrent, `-` reading it from stdin.
+    if let Some(path) = args.first() {
+        return cli::run(path, &config);
+    }
+
+    tracing_subscriber::fmt::init();
+
+    let app = app(config);
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::HttpBody;
+    use axum::http::header::CONTENT_TYPE;
+    use tower::ServiceExt;
+
+    const FIXTURE: &[u8] = include_bytes!("../tests/fixtures/v2_multi_file.torrent");
+
+    /// Uploads `data` to `/torrent` of a fresh deterministic app.
+    async fn upload(data: &[u8]) -> (StatusCode, HeaderMap, Vec<u8>) {
+        let mut config = Config::from_env();
+        config.deterministic = true;
+        config.store_dir = std::env::temp_dir().join("torrent-inspector-deterministic");
+
+        let mut body = b"--boundary\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"x.torrent\"\r\n\r\n"
+            .to_vec();
+        body.extend(data);
+        body.extend(b"\r\n--boundary--\r\n");
+        let mut request = Request::post("/torrent")
+            .header(CONTENT_TYPE, "multipart/form-data; boundary=boundary")
+            .body(Body::from(body))
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 1234))));
+
+        let response = app(config).oneshot(request).await.unwrap();
+        let (parts, mut body) = response.into_parts();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            bytes.extend(chunk.unwrap());
+        }
+        (parts.status, parts.headers, bytes)
+    }
+
+    /// A torrent of `files` one byte files.
+    fn many_files(files: usize) -> Vec<u8> {
+        let mut data = b"d4:infod5:filesl".to_vec();
+        for i in 0..files {
+            data.extend(format!("d6:lengthi1e4:pathl{}:{i}ee", i.to_string().len()).as_bytes());
+        }
+        data.extend(b"e4:name1:x12:piece lengthi16384e6:pieces20:");
+        data.extend([0xab; 20]);
+        data.extend(b"ee");
+        data
+    }
+
+    fn capped(truncate_files: bool) -> Config {
+        let mut config = Config::from_env();
+        config.max_files = 2;
+        config.truncate_files = truncate_files;
+        config
+    }
+
+    #[test]
+    fn files_past_the_cap_are_never_decoded() {
+        // The last file has a string for its length, so it fails to parse if it is decoded.
+        let mut data = many_files(2);
+        let files_end = data.len() - b"e4:name1:x12:piece lengthi16384e6:pieces20:".len() - 22;
+        data.splice(
+            files_end..files_end,
+            b"d6:length3:one4:pathl1:xee".iter().copied(),
+        );
+        let error = parse_torrent(&data, &Config::from_env()).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidMetainfo);
+
+        let torrent = parse_torrent(&data, &capped(true)).unwrap();
+        assert_eq!(torrent.info.files.unwrap().len(), 2);
+        assert_eq!(torrent.info.files_omitted, 1);
+    }
+
+    #[test]
+    fn too_many_files_are_refused_or_truncated() {
+        let data = many_files(3);
+        let error = parse_torrent(&data, &capped(false)).unwrap_err();
+        assert_eq!(error.code, ErrorCode::TooManyFiles);
+
+        let torrent = parse_torrent(&data, &capped(true)).unwrap();
+        assert_eq!(torrent.info.files.unwrap().len(), 2);
+        assert_eq!(torrent.info.files_omitted, 1);
+    }
+
+    #[tokio::test]
+    async fn saved_torrents_are_capped_too() {
+        let dir =
+            std::env::temp_dir().join(format!("torrent-inspector-capped-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("many.torrent");
+        std::fs::write(&path, many_files(3)).unwrap();
+
+        let config = Arc::new(capped(false));
+        assert!(Store::load(&path, config.clone()).await.is_none());
+        assert!(Store::load(&path, Arc::new(capped(true))).await.is_some());
+        assert!(Store::verify(&path, config).await.is_some());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Saves [`FIXTURE`] into a fresh store for `test`, returning its info-hash and path.
+    fn saved_fixture(config: &mut Config, test: &str) -> (String, std::path::PathBuf) {
+        config.store_dir =
+            std::env::temp_dir().join(format!("torrent-inspector-{test}-{}", std::process::id()));
+        let torrent: Torrent = serde_bencode::from_bytes(FIXTURE).unwrap();
+        let key = Store::key(FIXTURE, &torrent.info).unwrap();
+        let path = Store::new(&config.store_dir).path(&key).unwrap();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, FIXTURE).unwrap();
+        (key, path)
+    }
+
+    #[tokio::test]
+    async fn deterministic_mode_refuses_to_delete() {
+        let mut config = Config::from_env();
+        config.deterministic = true;
+        config.admin_token = Some("secret".to_string());
+        let (key, path) = saved_fixture(&mut config, "no-delete");
+
+        let request = Request::delete(format!("/torrent/{key}"))
+            .header("authorization", "Bearer secret")
+            .body(Body::empty())
+            .unwrap();
+        let response = app(config.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(path.exists());
+        let _ = std::fs::remove_dir_all(&config.store_dir);
+    }
+
+    #[tokio::test]
+    async fn summaries_are_revalidated_with_their_etag() {
+        let mut config = Config::from_env();
+        let (key, path) = saved_fixture(&mut config, "etag");
+
+        let app = app(config.clone());
+        let get = |etag: Option<&HeaderValue>| {
+            let mut request = Request::get(format!("/torrent/{key}/summary"));
+            if let Some(etag) = etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+        let response = get(None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CACHE_CONTROL], "public, max-age=60");
+        let etag = response.headers()[ETAG].clone();
+        let response = get(Some(&etag)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], etag);
+
+        // Replacing the torrent under the same info-hash changes the tag.
+        let mut replaced = FIXTURE[..FIXTURE.len() - 1].to_vec();
+        replaced.extend(b"7:comment1:xe");
+        std::fs::write(&path, &replaced).unwrap();
+        let response = get(Some(&etag)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()[ETAG], etag);
+        let _ = std::fs::remove_dir_all(&config.store_dir);
+    }
+
+    #[tokio::test]
+    async fn safe_mode_refuses_to_fetch() {
+        let mut config = Config::from_env();
+        config.safe_mode = true;
+        // Without safe mode, this is refused with 400 for not being a public address.
+        let request = Request::post("/torrent/fetch?url=http://127.0.0.1:9/x.torrent")
+            .body(Body::empty())
+            .unwrap();
+        let response = app(config).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn deterministic_responses_are_identical() {
+        let first = upload(FIXTURE).await;
+        let second = upload(FIXTURE).await;
+        assert_eq!(first.0, StatusCode::OK);
+        assert_eq!(first, second);
+    }
 }
@@ -0,0 +1,81 @@
+//! Rewriting a torrent into a canonical form, for telling apart torrents that only differ
+//! in how their trackers are listed, or without its trackers altogether.
+
+use crate::bencode;
+use crate::hash;
+use crate::torrent::Torrent;
+
+/// Rebuilds the top-level dictionary of a torrent. The transformations are exactly:
+///
+/// - Keys are sorted bytewise, as bencode requires; of duplicate keys only the first is kept.
+/// - Empty URLs and the tiers left empty without them are removed from **announce-list**.
+/// - **announce** is folded into **announce-list**: when it is not in any tier, it is added as a
+///   tier of its own in front, as clients reading **announce-list** would otherwise never use it.
+///   **announce** itself stays, for clients that predate BEP 12.
+///
+/// Every other value, the info dictionary above all, is copied byte for byte, which keeps the
+/// info-hashes as they were. Returns `None` if they changed regardless, or the data is not
+/// a dictionary.
+pub(crate) fn normalize(data_raw: &[u8], torrent: &Torrent) -> Option<Vec<u8>> {
+    let mut announce_list = torrent
+        .cleaned_announce_list()
+        .unwrap_or_else(|| torrent.announce_list.clone());
+    if let Some(announce) = torrent
+        .announce
+        .as_ref()
+        .map(|a| &a.url)
+        .filter(|a| !a.is_empty())
+    {
+        if !announce_list
+            .iter()
+            .flatten()
+            .any(|tracker| tracker == announce)
+        {
+            announce_list.insert(0, vec![announce.clone()]);
+        }
+    }
+
+    let mut entries: Vec<(&[u8], &[u8])> = bencode::dict_entries(data_raw)?
+        .into_iter()
+        .filter(|(key, _)| *key != b"announce-list")
+        .map(|(key, range)| (key, &data_raw[range]))
+        .collect();
+    let announce_list = serde_bencode::to_bytes(&announce_list).ok()?;
+    if announce_list != b"le" {
+        entries.push((b"announce-list", &announce_list));
+    }
+    // A stable sort, so the first of duplicate keys stays first.
+    entries.sort_by_key(|(key, _)| *key);
+    entries.dedup_by(|(a, _), (b, _)| a == b);
+
+    rebuild(data_raw, entries)
+}
+
+/// Removes **announce** and **announce-list**, leaving a torrent that can only find peers
+/// over DHT and peer exchange. Everything else is copied byte for byte, in its original order.
+///
+/// Returns `None` if the info-hashes changed regardless, or the data is not a dictionary.
+pub(crate) fn strip_trackers(data_raw: &[u8]) -> Option<Vec<u8>> {
+    let entries = bencode::dict_entries(data_raw)?
+        .into_iter()
+        .filter(|(key, _)| *key != b"announce" && *key != b"announce-list")
+        .map(|(key, range)| (key, &data_raw[range]))
+        .collect();
+    rebuild(data_raw, entries)
+}
+
+/// Encodes `entries` as a dictionary, as long as that keeps the info-hashes of `data_raw`.
+fn rebuild(data_raw: &[u8], entries: Vec<(&[u8], &[u8])>) -> Option<Vec<u8>> {
+    let mut rebuilt = vec![b'd'];
+    for (key, value) in entries {
+        rebuilt.extend_from_slice(key.len().to_string().as_bytes());
+        rebuilt.push(b':');
+        rebuilt.extend_from_slice(key);
+        rebuilt.extend_from_slice(value);
+    }
+    rebuilt.push(b'e');
+
+    let unchanged = hash::info_hash_v1(data_raw) == hash::info_hash_v1(&rebuilt)
+        && hash::info_hash_v2(data_raw) == hash::info_hash_v2(&rebuilt);
+    unchanged.then_some(rebuilt)
+}
@@ -0,0 +1,62 @@
+//! The files two torrents have in common, for cross-seeding torrents that only partially match.
+
+use crate::torrent::Info;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// A file matched by its path and length.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub(crate) struct OverlapFile {
+    /// Joined with `/`, relative to the torrent root, so a renamed root folder still matches.
+    pub path: String,
+    pub length: u64,
+}
+
+/// Which files are in both torrents and which in only one of them, each in the order listed.
+#[derive(Debug, Serialize)]
+pub(crate) struct Overlap {
+    pub shared_count: usize,
+    /// The bytes of content in `shared`, or `None` if their lengths overflow.
+    pub shared_size: Option<u64>,
+    pub shared: Vec<OverlapFile>,
+    pub only_first: Vec<OverlapFile>,
+    pub only_second: Vec<OverlapFile>,
+}
+
+impl Overlap {
+    pub fn new(first: &Info, second: &Info) -> Self {
+        let first = files(first);
+        let second = files(second);
+        let in_first: HashSet<&OverlapFile> = first.iter().collect();
+        let in_second: HashSet<&OverlapFile> = second.iter().collect();
+
+        let (shared, only_first): (Vec<_>, Vec<_>) = first
+            .iter()
+            .cloned()
+            .partition(|file| in_second.contains(file));
+        let only_second = second
+            .iter()
+            .filter(|file| !in_first.contains(file))
+            .cloned()
+            .collect();
+        Overlap {
+            shared_count: shared.len(),
+            shared_size: shared
+                .iter()
+                .try_fold(0u64, |total, file| total.checked_add(file.length)),
+            shared,
+            only_first,
+            only_second,
+        }
+    }
+}
+
+fn files(info: &Info) -> Vec<OverlapFile> {
+    info.file_entries()
+        .into_iter()
+        .map(|file| OverlapFile {
+            path: file.path.join("/"),
+            length: file.length,
+        })
+        .collect()
+}
@@ -0,0 +1,137 @@
+use crate::torrent::Info;
+use serde::{Deserialize, Serialize};
+
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+const GIB: u64 = 1024 * MIB;
+
+/// How many doublings or halvings away from the recommendation a piece length
+/// may be before it is worth a warning.
+const MAX_DEVIATION: f64 = 2.0;
+
+/// The piece length that suits the size of a torrent's content.
+#[derive(Debug, Serialize)]
+pub(crate) struct PieceLengthRecommendation {
+    pub recommended: u64,
+    /// The smallest and largest piece lengths that are still a sensible fit.
+    pub min: u64,
+    pub max: u64,
+    /// log2 of the actual piece length over the recommended one:
+    /// `1.0` means twice as large, `-2.0` a quarter of the size.
+    pub deviation: f64,
+}
+
+impl PieceLengthRecommendation {
+    /// Recommends a piece length along the lines of what common tools pick, which keeps
+    /// the number of pieces at a few thousand at most: small pieces bloat the info dictionary with
+    /// hashes, large ones make every failed hash check throw away a lot of data.
+    pub fn new(total_size: u64, piece_length: i64) -> Self {
+        let recommended = match total_size {
+            size if size <= 50 * MIB => 32 * KIB,
+            size if size <= 150 * MIB => 64 * KIB,
+            size if size <= 350 * MIB => 128 * KIB,
+            size if size <= 512 * MIB => 256 * KIB,
+            size if size <= GIB => 512 * KIB,
+            size if size <= 2 * GIB => MIB,
+            size if size <= 4 * GIB => 2 * MIB,
+            size if size <= 8 * GIB => 4 * MIB,
+            size if size <= 16 * GIB => 8 * MIB,
+            _ => 16 * MIB,
+        };
+        let deviation = if piece_length > 0 {
+            (piece_length as f64 / recommended as f64).log2()
+        } else {
+            f64::NEG_INFINITY
+        };
+        PieceLengthRecommendation {
+            recommended,
+            min: recommended / 2,
+            max: recommended * 2,
+            deviation,
+        }
+    }
+
+    pub fn is_far_off(&self) -> bool {
+        self.deviation.abs() > MAX_DEVIATION
+    }
+}
+
+/// Whether to list every piece hash rather than up to the configured limit, as a query parameter.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct PieceDisplay {
+    pub all: bool,
+}
+
+impl PieceDisplay {
+    /// The most hashes to list, or `None` for all of them.
+    pub fn limit(&self, default: usize) -> Option<usize> {
+        (!self.all).then_some(default)
+    }
+}
+
+/// How `/torrent/pieces` encodes the hashes, as the `format` query parameter.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct PieceFormat {
+    pub format: PieceEncoding,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PieceEncoding {
+    /// A response listing the hashes in hex.
+    #[default]
+    Hex,
+    /// The raw hashes back to back, 20 bytes each, as `application/octet-stream`.
+    Binary,
+}
+
+/// The v1 SHA-1 piece hashes, in hex.
+#[derive(Debug, Serialize)]
+pub(crate) struct PieceHashes {
+    /// The number of whole hashes in **pieces**.
+    pub total: usize,
+    /// Whether `hashes` stops short of `total`.
+    pub truncated: bool,
+    pub hashes: Vec<String>,
+}
+
+impl PieceHashes {
+    pub fn new(info: &Info, limit: Option<usize>) -> Self {
+        let total = info.piece_hashes().count();
+        let hashes: Vec<String> = info
+            .piece_hashes()
+            .take(limit.unwrap_or(usize::MAX))
+            .map(hex::encode)
+            .collect();
+        PieceHashes {
+            total,
+            truncated: hashes.len() < total,
+            hashes,
+        }
+    }
+}
+
+/// log2 of `piece_length`, like 18 for 256 KiB, or `None` if it is not a power of two.
+pub(crate) fn length_exponent(piece_length: i64) -> Option<u32> {
+    let piece_length = u64::try_from(piece_length).ok()?;
+    piece_length
+        .is_power_of_two()
+        .then(|| piece_length.trailing_zeros())
+}
+
+/// Formats a byte count with the largest binary unit it reaches, like `256 KiB` or `1.5 GiB`.
+///
+/// Piece lengths are powers of two, so they always come out as whole numbers.
+pub(crate) fn human_size(bytes: u64) -> String {
+    let (unit, name) = [(GIB, "GiB"), (MIB, "MiB"), (KIB, "KiB")]
+        .into_iter()
+        .find(|(unit, _)| bytes >= *unit)
+        .unwrap_or((1, "B"));
+    if bytes.is_multiple_of(unit) {
+        format!("{} {name}", bytes / unit)
+    } else {
+        format!("{:.2} {name}", bytes as f64 / unit as f64)
+    }
+}
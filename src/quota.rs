@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A daily cap on how many bytes each client IP may upload, to keep the store from growing unchecked.
+///
+/// The usage resets at UTC midnight. When a file is configured, the usage is written to it
+/// after every upload and read back at startup, so a restart does not hand out fresh quotas.
+#[derive(Debug)]
+pub(crate) struct Quota {
+    /// Bytes per IP per day, or `None` to not limit uploads at all.
+    limit: Option<u64>,
+    file: Option<PathBuf>,
+    usage: Mutex<Usage>,
+    /// Tells the current day, which is frozen in deterministic mode.
+    today: fn() -> u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Usage {
+    /// Days since the Unix epoch, in UTC.
+    day: u64,
+    bytes: HashMap<IpAddr, u64>,
+}
+
+fn today() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_secs() / 86400
+}
+
+impl Quota {
+    pub fn new(limit: Option<u64>, file: Option<PathBuf>) -> Self {
+        let usage = file
+            .as_ref()
+            .and_then(|file| std::fs::read(file).ok())
+            .and_then(|usage| serde_json::from_slice(&usage).ok())
+            .unwrap_or_default();
+        Quota {
+            limit,
+            file,
+            usage: Mutex::new(usage),
+            today,
+        }
+    }
+
+    /// A quota that is kept in memory and never resets, so it does not depend on the clock.
+    pub fn frozen(limit: Option<u64>) -> Self {
+        Quota {
+            limit,
+            file: None,
+            usage: Mutex::default(),
+            today: || 0,
+        }
+    }
+
+    /// Counts an upload against the quota of `ip`.
+    ///
+    /// Returns `false`, without counting it, if the upload would take the IP over its quota.
+    pub fn charge(&self, ip: IpAddr, bytes: u64) -> bool {
+        let Some(limit) = self.limit else {
+            return true;
+        };
+        let mut usage = self.usage.lock().unwrap();
+        let today = (self.today)();
+        if usage.day != today {
+            usage.day = today;
+            usage.bytes.clear();
+        }
+
+        let used = usage.bytes.entry(ip).or_default();
+        if used.saturating_add(bytes) > limit {
+            return false;
+        }
+        *used += bytes;
+
+        if let Some(file) = &self.file {
+            if let Ok(usage) = serde_json::to_vec(&*usage) {
+                let _ = std::fs::write(file, usage);
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,140 @@
+//! Compact, URL-safe references to torrents, for passing them around in links.
+//!
+//! A reference is the base64url encoding (without padding) of this layout:
+//!
+//! ```text
+//! u8      layout version, currently 1
+//! u8      flags: bit 0 set if a v1 info-hash follows, bit 1 set if a v2 info-hash follows
+//! [u8;20] v1 info-hash (SHA-1), if flagged
+//! [u8;32] v2 info-hash (SHA-256), if flagged
+//! varint  length of the name in bytes, followed by the UTF-8 name
+//! varint  number of trackers, followed by each tracker as a varint length and UTF-8 URL
+//! ```
+//!
+//! Varints are unsigned LEB128: 7 bits per byte, least significant first, high bit set on all but the last byte.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::Serialize;
+
+const VERSION: u8 = 1;
+const HAS_V1: u8 = 1 << 0;
+const HAS_V2: u8 = 1 << 1;
+
+/// The essentials of a torrent, enough to build a magnet link from.
+#[derive(Debug, Serialize)]
+pub(crate) struct Reference {
+    pub v1: Option<String>,
+    pub v2: Option<String>,
+    pub name: String,
+    pub trackers: Vec<String>,
+}
+
+impl Reference {
+    pub fn encode(&self) -> String {
+        let v1 = self.v1.as_deref().and_then(|v1| hex::decode(v1).ok());
+        let v2 = self.v2.as_deref().and_then(|v2| hex::decode(v2).ok());
+
+        let mut flags = 0;
+        if v1.is_some() {
+            flags |= HAS_V1;
+        }
+        if v2.is_some() {
+            flags |= HAS_V2;
+        }
+        let mut bytes = vec![VERSION, flags];
+        bytes.extend(v1.into_iter().flatten());
+        bytes.extend(v2.into_iter().flatten());
+        write_string(&mut bytes, &self.name);
+        write_varint(&mut bytes, self.trackers.len() as u64);
+        for tracker in &self.trackers {
+            write_string(&mut bytes, tracker);
+        }
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    pub fn decode(encoded: &str) -> Result<Self, String> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(encoded)
+            .map_err(|_| "Reference is not valid base64url".to_string())?;
+        let mut reader = Reader(&bytes);
+        let invalid = || "Reference is truncated or malformed".to_string();
+
+        let version = reader.take(1).ok_or_else(invalid)?[0];
+        if version != VERSION {
+            return Err(format!("Unsupported reference version {version}"));
+        }
+        let flags = reader.take(1).ok_or_else(invalid)?[0];
+        let v1 = match flags & HAS_V1 {
+            0 => None,
+            _ => Some(hex::encode(reader.take(20).ok_or_else(invalid)?)),
+        };
+        let v2 = match flags & HAS_V2 {
+            0 => None,
+            _ => Some(hex::encode(reader.take(32).ok_or_else(invalid)?)),
+        };
+        let name = reader.string().ok_or_else(invalid)?;
+        let count = reader.varint().ok_or_else(invalid)?;
+        let mut trackers = Vec::new();
+        for _ in 0..count {
+            trackers.push(reader.string().ok_or_else(invalid)?);
+        }
+        if !reader.0.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Reference {
+            v1,
+            v2,
+            name,
+            trackers,
+        })
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn write_string(bytes: &mut Vec<u8>, string: &str) {
+    write_varint(bytes, string.len() as u64);
+    bytes.extend_from_slice(string.as_bytes());
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = usize::try_from(self.varint()?).ok()?;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+}
@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+
+/// Resume data, as libtorrent (and so qBittorrent and Deluge) keeps it in `.fastresume` files next to torrents.
+///
+/// [https://www.libtorrent.org/manual-ref.html#fast-resume]
+///
+/// Only the commonly useful keys are modeled; everything else is ignored.
+#[derive(Debug, Deserialize)]
+pub(crate) struct Resume {
+    /// Always `libtorrent resume file`.
+    #[serde(default)]
+    #[serde(rename = "file-format")]
+    pub file_format: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "file-version")]
+    pub file_version: Option<i64>,
+    #[serde(default)]
+    #[serde(rename = "libtorrent-version")]
+    pub libtorrent_version: Option<String>,
+    /// The v1 info-hash of the torrent this belongs to.
+    #[serde(default, with = "serde_bytes")]
+    #[serde(rename = "info-hash")]
+    pub info_hash: Option<Vec<u8>>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub save_path: Option<String>,
+    #[serde(default)]
+    pub total_uploaded: Option<i64>,
+    #[serde(default)]
+    pub total_downloaded: Option<i64>,
+    /// Seconds the torrent has been active for.
+    #[serde(default)]
+    pub active_time: Option<i64>,
+    #[serde(default)]
+    pub seeding_time: Option<i64>,
+    /// Unix timestamps.
+    #[serde(default)]
+    pub added_time: Option<i64>,
+    #[serde(default)]
+    pub completed_time: Option<i64>,
+    /// One byte per piece, with the lowest bit set when the piece has been downloaded and checked.
+    #[serde(default, with = "serde_bytes")]
+    pub pieces: Option<Vec<u8>>,
+    /// One priority per file, from 0 (do not download) to 7.
+    #[serde(default)]
+    pub file_priority: Vec<i64>,
+    /// One byte of priority per piece.
+    #[serde(default, with = "serde_bytes")]
+    pub piece_priority: Option<Vec<u8>>,
+    /// Tracker tiers, like **announce-list**.
+    #[serde(default)]
+    pub trackers: Vec<Vec<String>>,
+    #[serde(default)]
+    #[serde(rename = "url-list")]
+    pub url_list: Vec<String>,
+    #[serde(default)]
+    pub paused: Option<i64>,
+    #[serde(default)]
+    pub auto_managed: Option<i64>,
+    #[serde(default)]
+    pub sequential_download: Option<i64>,
+    #[serde(default)]
+    pub upload_rate_limit: Option<i64>,
+    #[serde(default)]
+    pub download_rate_limit: Option<i64>,
+    /// The category qBittorrent files the torrent under.
+    #[serde(default)]
+    #[serde(rename = "qBt-category")]
+    pub qbt_category: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "qBt-tags")]
+    pub qbt_tags: Vec<String>,
+}
+
+/// What the resume data says about the state of the download.
+#[derive(Debug, Serialize)]
+pub(crate) struct ResumeSummary {
+    pub file_format: Option<String>,
+    pub file_version: Option<i64>,
+    pub libtorrent_version: Option<String>,
+    pub info_hash: Option<String>,
+    pub name: Option<String>,
+    pub save_path: Option<String>,
+    pub progress: Progress,
+    pub total_uploaded: Option<i64>,
+    pub total_downloaded: Option<i64>,
+    pub active_time: Option<i64>,
+    pub seeding_time: Option<i64>,
+    pub added_time: Option<i64>,
+    pub completed_time: Option<i64>,
+    pub paused: bool,
+    pub auto_managed: bool,
+    pub sequential_download: bool,
+    pub upload_rate_limit: Option<i64>,
+    pub download_rate_limit: Option<i64>,
+    pub file_priority: Vec<i64>,
+    pub piece_priority: Vec<u8>,
+    pub trackers: Vec<Vec<String>>,
+    pub web_seeds: Vec<String>,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Progress {
+    pub pieces_have: usize,
+    pub pieces_total: usize,
+    /// From `0.0` to `1.0`, or `None` if the resume data does not track pieces.
+    pub fraction: Option<f64>,
+}
+
+impl From<Resume> for ResumeSummary {
+    fn from(resume: Resume) -> Self {
+        let pieces = resume.pieces.unwrap_or_default();
+        let pieces_have = pieces.iter().filter(|piece| *piece & 1 == 1).count();
+        let progress = Progress {
+            pieces_have,
+            pieces_total: pieces.len(),
+            fraction: (!pieces.is_empty()).then(|| pieces_have as f64 / pieces.len() as f64),
+        };
+        ResumeSummary {
+            file_format: resume.file_format,
+            file_version: resume.file_version,
+            libtorrent_version: resume.libtorrent_version,
+            info_hash: resume.info_hash.map(hex::encode),
+            name: resume.name,
+            save_path: resume.save_path,
+            progress,
+            total_uploaded: resume.total_uploaded,
+            total_downloaded: resume.total_downloaded,
+            active_time: resume.active_time,
+            seeding_time: resume.seeding_time,
+            added_time: resume.added_time,
+            completed_time: resume.completed_time,
+            paused: resume.paused.unwrap_or_default() != 0,
+            auto_managed: resume.auto_managed.unwrap_or_default() != 0,
+            sequential_download: resume.sequential_download.unwrap_or_default() != 0,
+            upload_rate_limit: resume.upload_rate_limit,
+            download_rate_limit: resume.download_rate_limit,
+            file_priority: resume.file_priority,
+            piece_priority: resume.piece_priority.unwrap_or_default(),
+            trackers: resume.trackers,
+            web_seeds: resume.url_list,
+            category: resume.qbt_category,
+            tags: resume.qbt_tags,
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! What clients make of a torrent's name when they create it on disk.
+
+use serde::{Deserialize, Serialize};
+
+/// The operating system whose filename rules a name is sanitized for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TargetOs {
+    /// The strictest, and so the default.
+    #[default]
+    Windows,
+    Macos,
+    Linux,
+}
+
+/// Device names Windows reserves in every directory, with or without an extension.
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Sanitizes a single path segment the way common clients do, replacing what the
+/// target cannot store with `_`.
+pub(crate) fn sanitize(name: &str, os: TargetOs) -> String {
+    let is_illegal = |c: char| match os {
+        TargetOs::Windows => c.is_control() || "<>:\"/\\|?*".contains(c),
+        TargetOs::Macos => c == '/' || c == ':' || c == '\0',
+        TargetOs::Linux => c == '/' || c == '\0',
+    };
+    let mut name: String = name
+        .chars()
+        .map(|c| if is_illegal(c) { '_' } else { c })
+        .collect();
+
+    if os == TargetOs::Windows {
+        // Windows silently drops these, so `name.` and `name` would be the same file.
+        name.truncate(name.trim_end_matches(['.', ' ']).len());
+        let stem = name.split('.').next().unwrap_or_default();
+        if RESERVED
+            .iter()
+            .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+        {
+            name.insert(0, '_');
+        }
+    }
+    if name.is_empty() || name == "." || name == ".." {
+        name = "_".to_string();
+    }
+    name
+}
@@ -0,0 +1,137 @@
+//! Scanning every saved torrent, with the progress streamed as Server-Sent Events.
+//!
+//! A `found` event is sent for each torrent file as the store's directories are read, and a
+//! `parsed` or `error` event once it has been loaded. Files are loaded `parse_workers` at a time,
+//! so `found` runs a little ahead of the rest. The stream ends with `done` and the totals.
+
+use crate::config::Config;
+use crate::error::{AppError, ErrorCode};
+use crate::store::Store;
+use crate::unreadable_store;
+use axum::extract::Extension;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use futures_util::future;
+use futures_util::stream::{self, StreamExt};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::path::Path;
+use std::pin::pin;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// How many files were seen by the end of a scan.
+#[derive(Debug, Default, Serialize)]
+struct Totals {
+    found: usize,
+    parsed: usize,
+    failed: usize,
+}
+
+/// Scans the store, sending the progress as it goes.
+pub(crate) async fn scan(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(store): Extension<Store>,
+) -> Result<Response, AppError> {
+    let paths = store.paths().await.map_err(|_| unreadable_store())?;
+
+    let (events, received) = mpsc::channel(64);
+    tokio::spawn(async move {
+        // Every send fails once the client has gone away, which ends the scan.
+        let found = events.clone();
+        let workers = config.parse_workers;
+        let loaded = paths
+            .then(move |path| {
+                let found = found.clone();
+                async move {
+                    let sent = found.send(event("found", &File::of(&path))).await;
+                    sent.ok().map(|()| path)
+                }
+            })
+            .take_while(|path| future::ready(path.is_some()))
+            .filter_map(future::ready)
+            .map(move |path| {
+                let config = config.clone();
+                async move {
+                    let loaded = Store::load(&path, config).await;
+                    (path, loaded)
+                }
+            })
+            .buffered(workers);
+        let mut loaded = pin!(loaded);
+
+        let mut totals = Totals::default();
+        while let Some((path, loaded)) = loaded.next().await {
+            totals.found += 1;
+            let event = match loaded {
+                Some(torrent) => {
+                    totals.parsed += 1;
+                    event("parsed", &torrent)
+                }
+                None => {
+                    totals.failed += 1;
+                    event("error", &ScanError::of(&path))
+                }
+            };
+            if events.send(event).await.is_err() {
+                return;
+            }
+        }
+        // The last event, so there is nothing left to stop if it cannot be sent.
+        let _ = events.send(event("done", &totals)).await;
+    });
+
+    let events = stream::unfold(received, |mut received| async move {
+        let event = received.recv().await?;
+        Some((Ok::<_, Infallible>(event), received))
+    });
+    Ok(Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response())
+}
+
+/// A torrent file found in the store.
+#[derive(Serialize)]
+struct File {
+    file: String,
+}
+
+impl File {
+    fn of(path: &Path) -> Self {
+        File {
+            file: file_name(path),
+        }
+    }
+}
+
+/// A torrent file that could not be loaded, shaped like a failure.
+#[derive(Serialize)]
+struct ScanError {
+    file: String,
+    code: ErrorCode,
+    message: String,
+}
+
+impl ScanError {
+    fn of(path: &Path) -> Self {
+        ScanError {
+            file: file_name(path),
+            code: ErrorCode::InvalidMetainfo,
+            message: format!("Failed to parse {}", path.display()),
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// An event named `name`, with `data` as JSON.
+fn event(name: &str, data: &impl Serialize) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(data)
+        .unwrap_or_else(|_| Event::default().event(name))
+}
@@ -0,0 +1,330 @@
+use crate::config::Config;
+use crate::hash::{self, HashSelection, InfoHashes};
+use crate::parse_torrent;
+use crate::sanitize::{self, TargetOs};
+use crate::torrent::Info;
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The directory uploaded torrents are saved into.
+///
+/// Torrents are addressed by their info-hash, and sharded into subdirectories by its first
+/// two hex characters so no single directory grows too large: `ab/abcdef….torrent`.
+#[derive(Debug, Clone)]
+pub(crate) struct Store {
+    dir: PathBuf,
+    writes: Writes,
+}
+
+/// What a store does when asked to write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Writes {
+    Allowed,
+    /// Writes succeed without touching the disk, so saved torrents can be read but no new ones added.
+    ///
+    /// Deleting fails with [`io::ErrorKind::PermissionDenied`] instead, as the torrent would
+    /// still be there after being reported gone.
+    Skipped,
+    /// Like `Skipped`, except that writes the user asked for explicitly, like [`Store::save_as`],
+    /// fail with [`io::ErrorKind::PermissionDenied`] rather than pretend to succeed.
+    Refused,
+}
+
+/// Where to additionally save an upload, as a query parameter or multipart field.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct SaveOptions {
+    /// A file name of the user's choosing, for keeping a collection under their own names.
+    pub save_as: Option<String>,
+}
+
+/// A short description of a saved torrent.
+#[derive(Debug, Serialize)]
+pub(crate) struct StoredTorrent {
+    pub file: String,
+    pub name: String,
+    pub info_hash: InfoHashes,
+    /// `None` if the file lengths overflow.
+    pub total_size: Option<u64>,
+    pub file_count: usize,
+}
+
+/// Which saved torrents to list, as query parameters.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct Search {
+    /// Only torrents whose name contains this, ignoring case.
+    pub q: Option<String>,
+}
+
+/// A page of the saved torrents, with the number matching the search.
+#[derive(Debug, Serialize)]
+pub(crate) struct Listing {
+    pub total: usize,
+    pub offset: usize,
+    pub torrents: Vec<StoredTorrent>,
+}
+
+/// A saved torrent whose contents do not hash to the name it is saved under.
+#[derive(Debug, Serialize)]
+pub(crate) struct Mismatch {
+    pub file: String,
+    /// The info-hash in the file name.
+    pub expected: String,
+    /// The info-hash it should be saved under, or `None` if it no longer parses.
+    pub computed: Option<String>,
+}
+
+/// The outcome of rehashing every saved torrent.
+#[derive(Debug, Serialize)]
+pub(crate) struct Audit {
+    pub checked: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl Store {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Store {
+            dir: dir.into(),
+            writes: Writes::Allowed,
+        }
+    }
+
+    /// A store that never writes to `dir`.
+    pub fn read_only(dir: impl Into<PathBuf>) -> Self {
+        Store {
+            dir: dir.into(),
+            writes: Writes::Skipped,
+        }
+    }
+
+    /// A store that never writes to `dir`, and says so when a write was asked for.
+    pub fn safe(dir: impl Into<PathBuf>) -> Self {
+        Store {
+            dir: dir.into(),
+            writes: Writes::Refused,
+        }
+    }
+
+    /// The path a torrent is stored at, or `None` if `info_hash` is not a hex info-hash.
+    pub fn path(&self, info_hash: &str) -> Option<PathBuf> {
+        let is_hex = info_hash.bytes().all(|b| b.is_ascii_hexdigit());
+        if !is_hex || !(info_hash.len() == 40 || info_hash.len() == 64) {
+            return None;
+        }
+        let info_hash = info_hash.to_ascii_lowercase();
+        Some(
+            self.dir
+                .join(&info_hash[..2])
+                .join(format!("{info_hash}.torrent")),
+        )
+    }
+
+    /// Saves the raw torrent under its info-hash, creating its shard directory as needed.
+    pub fn save(&self, info_hash: &str, data_raw: &[u8]) -> io::Result<()> {
+        let path = self
+            .path(info_hash)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid info-hash"))?;
+        if self.writes != Writes::Allowed {
+            return Ok(());
+        }
+        if let Some(shard) = path.parent() {
+            std::fs::create_dir_all(shard)?;
+        }
+        let mut file = std::fs::File::create(path)?;
+        file.write_all(data_raw)
+    }
+
+    /// Makes an already saved torrent also available as `named/<name>.torrent`, replacing
+    /// whatever had that name before.
+    ///
+    /// The name is sanitized like a file name on Windows, so it cannot point anywhere else.
+    /// The copy is a hard link where the file system allows.
+    pub fn save_as(&self, info_hash: &str, name: &str) -> io::Result<PathBuf> {
+        let path = self
+            .path(info_hash)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid info-hash"))?;
+        let name = sanitize::sanitize(name, TargetOs::Windows);
+        let name = match name.to_ascii_lowercase().ends_with(".torrent") {
+            true => name,
+            false => format!("{name}.torrent"),
+        };
+        let named = self.dir.join("named").join(name);
+        match self.writes {
+            Writes::Allowed => {}
+            Writes::Skipped => return Ok(named),
+            Writes::Refused => return Err(io::ErrorKind::PermissionDenied.into()),
+        }
+        std::fs::create_dir_all(self.dir.join("named"))?;
+        let _ = std::fs::remove_file(&named);
+        if std::fs::hard_link(&path, &named).is_err() {
+            std::fs::copy(&path, &named)?;
+        }
+        Ok(named)
+    }
+
+    /// Removes a saved torrent, returning whether there was one.
+    ///
+    /// Copies saved with [`Store::save_as`] are kept.
+    pub async fn delete(&self, info_hash: &str) -> io::Result<bool> {
+        let path = self
+            .path(info_hash)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid info-hash"))?;
+        match self.writes {
+            Writes::Allowed => {}
+            Writes::Skipped | Writes::Refused => return Err(io::ErrorKind::PermissionDenied.into()),
+        }
+        match tokio::fs::remove_file(path).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads a saved torrent back.
+    pub async fn read(&self, info_hash: &str) -> io::Result<Vec<u8>> {
+        let path = self
+            .path(info_hash)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "invalid info-hash"))?;
+        tokio::fs::read(path).await
+    }
+
+    /// The info-hashes of saved torrents starting with `prefix`, in order.
+    ///
+    /// Thanks to the sharding only a single directory has to be read, which is also why
+    /// the prefix must be at least two hex characters long.
+    pub async fn find(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let is_hex = prefix.bytes().all(|b| b.is_ascii_hexdigit());
+        if !is_hex || !(2..=64).contains(&prefix.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid prefix",
+            ));
+        }
+        let prefix = prefix.to_ascii_lowercase();
+        let mut shard = match tokio::fs::read_dir(self.dir.join(&prefix[..2])).await {
+            Ok(shard) => shard,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut info_hashes = Vec::new();
+        while let Some(entry) = shard.next_entry().await? {
+            let file_name = entry.file_name();
+            let info_hash = file_name.to_string_lossy();
+            if let Some(info_hash) = info_hash.strip_suffix(".torrent") {
+                if info_hash.starts_with(&prefix) {
+                    info_hashes.push(info_hash.to_string());
+                }
+            }
+        }
+        info_hashes.sort();
+        Ok(info_hashes)
+    }
+
+    /// Opens a saved torrent for streaming it, along with its size.
+    pub async fn open(&self, info_hash: &str) -> io::Result<(tokio::fs::File, u64)> {
+        let path = self
+            .path(info_hash)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "invalid info-hash"))?;
+        let file = tokio::fs::File::open(path).await?;
+        let size = file.metadata().await?.len();
+        Ok((file, size))
+    }
+
+    /// Lists the saved torrents as the shard directories are read, without waiting for all of them.
+    pub async fn paths(&self) -> io::Result<impl Stream<Item = PathBuf>> {
+        let root = tokio::fs::read_dir(&self.dir).await?;
+        // The directories left to read, innermost last.
+        let entries = stream::unfold(vec![(root, 0)], |mut dirs| async move {
+            loop {
+                let (dir, depth) = dirs.last_mut()?;
+                let depth = *depth;
+                match dir.next_entry().await {
+                    Ok(Some(entry)) => {
+                        let path = entry.path();
+                        let is_dir = entry.file_type().await.is_ok_and(|t| t.is_dir());
+                        // Only shards, not `named/` or anything else sharing the directory.
+                        let is_shard = entry.file_name().to_str().is_some_and(|name| {
+                            name.len() == 2 && name.bytes().all(|b| b.is_ascii_hexdigit())
+                        });
+                        if depth == 0 && is_dir && is_shard {
+                            if let Ok(shard) = tokio::fs::read_dir(&path).await {
+                                dirs.push((shard, 1));
+                            }
+                        } else if depth == 1 {
+                            return Some((path, dirs));
+                        }
+                    }
+                    _ => {
+                        dirs.pop();
+                    }
+                }
+            }
+        });
+        Ok(entries.filter(|path| {
+            let is_torrent = path.extension().is_some_and(|ext| ext == "torrent");
+            async move { is_torrent }
+        }))
+    }
+
+    /// The info-hash a torrent is stored under: the v1 one, or the v2 one for pure v2 torrents.
+    pub fn key(data_raw: &[u8], info: &Info) -> Option<String> {
+        if info.has_v1() {
+            hash::info_hash_v1(data_raw).map(hex::encode)
+        } else {
+            hash::info_hash_v2(data_raw).map(hex::encode)
+        }
+    }
+
+    /// Rehashes a saved torrent, returning how it differs from its file name if it does.
+    ///
+    /// A name matching either info-hash counts, though only [`Store::key`] is ever used.
+    /// A torrent that does not parse within the limits of `config` never matches.
+    pub async fn verify(path: &Path, config: Arc<Config>) -> Option<Mismatch> {
+        let file = path.file_name()?.to_string_lossy().into_owned();
+        let expected = file.strip_suffix(".torrent")?.to_ascii_lowercase();
+        let data_raw = tokio::fs::read(path).await.ok()?;
+        tokio::task::spawn_blocking(move || {
+            let torrent = parse_torrent(&data_raw, &config).ok();
+            let computed = torrent
+                .as_ref()
+                .and_then(|torrent| Store::key(&data_raw, &torrent.info));
+            let matches = computed.as_ref() == Some(&expected)
+                || torrent.is_some_and(|torrent| {
+                    let hashes = InfoHashes::compute(&data_raw, &torrent.info, HashSelection::Both);
+                    hashes.v1.as_ref() == Some(&expected) || hashes.v2.as_ref() == Some(&expected)
+                });
+            (!matches).then_some(Mismatch {
+                file,
+                expected,
+                computed,
+            })
+        })
+        .await
+        .ok()?
+    }
+
+    /// Reads and parses a saved torrent.
+    ///
+    /// Parsing runs on the blocking thread pool, so that loading many at once spreads over cores.
+    /// Like an upload, a torrent with more than `MAX_FILES` files does not load.
+    pub async fn load(path: &Path, config: Arc<Config>) -> Option<StoredTorrent> {
+        let file = path.file_name()?.to_string_lossy().into_owned();
+        let data_raw = tokio::fs::read(path).await.ok()?;
+        tokio::task::spawn_blocking(move || {
+            let torrent = parse_torrent(&data_raw, &config).ok()?;
+            Some(StoredTorrent {
+                file,
+                info_hash: InfoHashes::compute(&data_raw, &torrent.info, HashSelection::Both),
+                total_size: torrent.info.total_size(),
+                file_count: torrent.info.file_count(),
+                name: torrent.info.name,
+            })
+        })
+        .await
+        .ok()?
+    }
+}
@@ -0,0 +1,47 @@
+//! How long the phases of handling a request took, for the `Server-Timing` header.
+
+use axum::http::HeaderValue;
+use axum::response::Response;
+use std::time::{Duration, Instant};
+
+const SERVER_TIMING: &str = "server-timing";
+
+/// The phases timed so far, in the order they ran, or `None` when not timing at all.
+///
+/// The default does not time anything, and neither does deterministic mode, where the
+/// durations would make otherwise identical responses differ.
+#[derive(Debug, Default)]
+pub(crate) struct Timings(Option<Vec<(&'static str, Duration)>>);
+
+impl Timings {
+    pub fn new(enabled: bool) -> Self {
+        Timings(enabled.then(Vec::new))
+    }
+
+    /// Runs `phase`, adding how long it took under `name`.
+    pub fn time<T>(&mut self, name: &'static str, phase: impl FnOnce() -> T) -> T {
+        let Some(phases) = &mut self.0 else {
+            return phase();
+        };
+        let start = Instant::now();
+        let result = phase();
+        phases.push((name, start.elapsed()));
+        result
+    }
+
+    /// Adds the `Server-Timing` header to `response`, with durations in milliseconds.
+    pub fn apply(&self, mut response: Response) -> Response {
+        let Some(phases) = &self.0 else {
+            return response;
+        };
+        let value = phases
+            .iter()
+            .map(|(name, duration)| format!("{name};dur={:.3}", duration.as_secs_f64() * 1000.0))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response.headers_mut().insert(SERVER_TIMING, value);
+        }
+        response
+    }
+}
@@ -1,4 +1,11 @@
-use serde::{Deserialize, Serialize};
+use crate::extra::{Extra, ExtraValue};
+use serde::de::{self, IgnoredAny, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_bytes::ByteBuf;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 /// Metainfo files (also known as .torrent files) are bencoded dictionaries with the following keys:
 ///
@@ -6,8 +13,8 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct Torrent {
     /// The URL of the tracker
-    #[serde(default)]
-    pub announce: Option<String>,
+    #[serde(default, deserialize_with = "announce")]
+    pub announce: Option<Announce>,
     /// Info dictionary
     pub info: Info,
 
@@ -59,14 +66,153 @@ pub(crate) struct Torrent {
     #[serde(default)]
     #[serde(rename = "creation date")]
     pub creation_date: Option<u64>,
+    /// The free-form strings below are decoded lossily, so that binary in one of them does not
+    /// fail the whole torrent. See [`lossy`].
+    #[serde(default, deserialize_with = "lossy")]
     #[serde(rename = "comment")]
     pub comment: Option<String>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lossy")]
     #[serde(rename = "created by")]
     pub created_by: Option<String>,
 
-    #[serde(default)]
+    #[serde(default, deserialize_with = "lossy")]
     pub encoding: Option<String>,
+
+    /// BitTorrent v2 piece layers
+    ///
+    /// A dictionary of strings. For each file in the file tree that is larger than the piece size
+    /// it contains one string value. The keys are the merkle roots while the values consist of
+    /// concatenated hashes of one layer within that merkle tree. The layer is chosen so that
+    /// one hash covers **piece length** bytes.
+    ///
+    /// The layers are too large to be worth returning as they are, so only a summary goes into responses.
+    #[serde(default)]
+    #[serde(rename = "piece layers", skip_serializing)]
+    pub piece_layers: BTreeMap<ByteBuf, ByteBuf>,
+
+    /// Any other keys, such as those of extensions not modeled here.
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+impl Torrent {
+    /// Every distinct tracker of either **announce** or **announce-list**.
+    pub fn trackers(&self) -> Vec<&str> {
+        let mut seen = HashSet::new();
+        self.announce
+            .iter()
+            .flat_map(Announce::urls)
+            .chain(self.announce_list.iter().flatten())
+            .map(String::as_str)
+            .filter(|tracker| seen.insert(*tracker))
+            .collect()
+    }
+
+    /// Every distinct tracker a client would announce to, across all tiers of [`Torrent::tiers`].
+    pub fn effective_trackers(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self.tiers()
+            .into_iter()
+            .flatten()
+            .filter(|tracker| seen.insert(tracker.clone()))
+            .collect()
+    }
+
+    /// **announce-list** without empty URLs and the tiers left empty by removing them,
+    /// or `None` when there is nothing to remove.
+    pub fn cleaned_announce_list(&self) -> Option<Vec<Vec<String>>> {
+        let is_clean = self
+            .announce_list
+            .iter()
+            .all(|tier| !tier.is_empty() && tier.iter().all(|url| !url.is_empty()));
+        if is_clean {
+            return None;
+        }
+        Some(
+            self.announce_list
+                .iter()
+                .map(|tier| tier.iter().filter(|url| !url.is_empty()).cloned().collect())
+                .filter(|tier: &Vec<String>| !tier.is_empty())
+                .collect(),
+        )
+    }
+
+    /// The web seeds of **url-list** (BEP 19) and **httpseeds** (BEP 17), which may each be
+    /// a single URL or a list of them. Empty URLs are skipped.
+    pub fn web_seeds(&self) -> Vec<String> {
+        ["url-list", "httpseeds"]
+            .into_iter()
+            .filter_map(|key| self.extra.get(key))
+            .flat_map(|value| match value {
+                ExtraValue::List(values) => values.iter().collect(),
+                value => vec![value],
+            })
+            .filter_map(|value| match value {
+                ExtraValue::Bytes(url) if !url.is_empty() => {
+                    Some(String::from_utf8_lossy(url).into_owned())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether a client could find peers at all: through a tracker, a web seed, or the DHT nodes
+    /// of a torrent that is not private. Clients do not use the DHT for private torrents.
+    pub fn is_discoverable(&self) -> bool {
+        self.tiers().iter().flatten().any(|url| !url.is_empty())
+            || !self.web_seeds().is_empty()
+            || (!self.nodes.is_empty() && self.info.private != Some(1))
+    }
+
+    /// The tracker tiers a client would use.
+    ///
+    /// Per BEP 12, `announce` is only used when **announce-list** is absent.
+    /// A list under `announce` then makes two tiers, see [`Announce`].
+    pub fn tiers(&self) -> Vec<Vec<String>> {
+        if !self.announce_list.is_empty() {
+            self.announce_list.clone()
+        } else if let Some(announce) = &self.announce {
+            let mut tiers = vec![vec![announce.url.clone()]];
+            if !announce.rest.is_empty() {
+                tiers.push(announce.rest.clone());
+            }
+            tiers
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// Decodes a string that may not be UTF-8, replacing invalid sequences with U+FFFD.
+///
+/// Validation reports strings with replacement characters in them, see
+/// [`crate::validate::Warning::LossyString`].
+fn lossy<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let Some(bytes) = Option::<ByteBuf>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    Ok(Some(match String::from_utf8(bytes.into_vec()) {
+        Ok(string) => string,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    }))
+}
+
+/// Reads **announce**, leaving it out when it has no URL at all, like an empty list.
+fn announce<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Announce>, D::Error> {
+    Ok(Option::<Announce>::deserialize(deserializer)?.filter(|announce| !announce.url.is_empty()))
+}
+
+/// Deserializes **root hash**, hex encoding it when it is 20 raw bytes.
+fn root_hash<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let Some(bytes) = Option::<ByteBuf>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    Ok(Some(match String::from_utf8(bytes.into_vec()) {
+        Ok(string) if string.len() != 20 => string,
+        Ok(string) => hex::encode(string),
+        Err(e) if e.as_bytes().len() == 20 => hex::encode(e.as_bytes()),
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    }))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -91,13 +237,32 @@ pub(crate) struct Info {
     pub piece_length: i64,
     /// **pieces** maps to a string whose length is a multiple of 20. It is to be subdivided into
     /// strings of length 20, each of which is the SHA1 hash of the piece at the corresponding index.
-    #[serde(with = "serde_bytes")]
+    ///
+    /// Pure v2 torrents hash their pieces per file in **file tree** instead, and leave this out.
+    #[serde(default, with = "serde_bytes")]
     pub pieces: Vec<u8>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "files")]
     /// This key is only present if no length key is present. One of the two must be present.
     /// If files is present the metafile represent a set of files which go in a directory structure.
     /// files maps to a list representing all files in to metafile.
     pub files: Option<Vec<File>>,
+    /// How many files were cut from the end of **files** because there were too many.
+    #[serde(skip)]
+    pub files_omitted: usize,
+    /// BitTorrent v2 file tree
+    ///
+    /// A tree of dictionaries where dictionary keys represent UTF-8 encoded path elements.
+    /// Entries with zero-length keys describe the properties of the composed path at that point.
+    /// The file tree is also used for single file torrents, as a tree containing only the file name.
+    #[serde(default)]
+    #[serde(rename = "file tree")]
+    pub file_tree: Option<BTreeMap<String, FileTreeNode>>,
+    /// The version of the metainfo format, which is `2` for v2 and hybrid torrents.
+    ///
+    /// Clients only read the v2 keys when this is set, so a **file tree** without it is ignored.
+    #[serde(default)]
+    #[serde(rename = "meta version")]
+    pub meta_version: Option<u8>,
 
     /// Private torrents
     ///
@@ -105,26 +270,557 @@ pub(crate) struct Info {
     /// of the torrent’s metainfo file. This is used to disable peer sharing mechanism such as DHT and PEX.
     #[serde(default)]
     pub private: Option<u8>,
+    /// A 32-character hexadecimal string corresponding to the MD5 sum of the file.
+    /// This is not used by BitTorrent at all, but it is included by some programs for greater compatibility.
     #[serde(default)]
     pub md5sum: Option<String>,
     #[serde(default)]
     pub path: Option<Vec<String>>,
-    #[serde(default)]
+    /// The SHA-1 root of a merkle tree over the pieces, which BEP 30 puts in place of **pieces**.
+    ///
+    /// Read as hex, whether it is the 20 raw bytes BEP 30 specifies or already hex encoded.
+    #[serde(default, deserialize_with = "root_hash")]
     #[serde(rename = "root hash")]
     pub root_hash: Option<String>,
+    /// Nonstandard download priorities, one per entry of **files**.
+    #[serde(default)]
+    #[serde(rename = "file-priority", alias = "priorities")]
+    pub file_priority: Option<Vec<i64>>,
+
+    /// Any other keys of the info dictionary.
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Info {
+    /// Copies priorities from **file-priority** into the files that do not have their own.
+    pub fn resolve_priorities(&mut self) {
+        let (Some(priorities), Some(files)) = (&self.file_priority, &mut self.files) else {
+            return;
+        };
+        for (file, priority) in files.iter_mut().zip(priorities) {
+            file.priority.get_or_insert(*priority);
+        }
+    }
+
+    /// Whether the torrent can be downloaded by v1 clients.
+    pub fn has_v1(&self) -> bool {
+        self.length.is_some() || self.files.is_some()
+    }
+
+    /// Whether the torrent can be downloaded by v2 clients.
+    pub fn has_v2(&self) -> bool {
+        self.meta_version == Some(2) && self.file_tree.is_some()
+    }
+
+    /// Which versions of the protocol the torrent is for.
+    pub fn version(&self) -> Option<Version> {
+        match (self.has_v1(), self.has_v2()) {
+            (true, false) if self.root_hash.is_some() => Some(Version::Merkle),
+            (true, false) => Some(Version::V1),
+            (false, true) => Some(Version::V2),
+            (true, true) => Some(Version::Hybrid),
+            (false, false) => None,
+        }
+    }
+
+    /// The SHA-1 hashes of the v1 pieces.
+    ///
+    /// A truncated trailing hash is left out, so a corrupt **pieces** still yields every whole hash.
+    pub fn piece_hashes(&self) -> impl Iterator<Item = &[u8]> {
+        self.pieces.chunks_exact(20)
+    }
+
+    /// The files of the v2 **file tree**, in tree order, with their full paths.
+    pub fn file_tree_files(&self) -> Vec<(Vec<&str>, &FileTreeFile)> {
+        fn walk<'a>(
+            tree: &'a BTreeMap<String, FileTreeNode>,
+            path: &mut Vec<&'a str>,
+            files: &mut Vec<(Vec<&'a str>, &'a FileTreeFile)>,
+        ) {
+            for (name, node) in tree {
+                path.push(name);
+                match node {
+                    FileTreeNode::File(file) => files.push((path.clone(), &file.file)),
+                    FileTreeNode::Directory(tree) => walk(tree, path, files),
+                }
+                path.pop();
+            }
+        }
+
+        let mut files = Vec::new();
+        if let Some(tree) = &self.file_tree {
+            walk(tree, &mut Vec::new(), &mut files);
+        }
+        files
+    }
+
+    /// Every **md5sum** in the info dictionary, along with the path of the file it belongs to.
+    pub fn md5sums(&self) -> Vec<(String, &str)> {
+        let single = self
+            .md5sum
+            .as_deref()
+            .map(|md5sum| (self.name.clone(), md5sum));
+        let files = self.files.iter().flatten().filter_map(|file| {
+            let md5sum = file.md5sum.as_deref()?;
+            Some((file.path.join("/"), md5sum))
+        });
+        single.into_iter().chain(files).collect()
+    }
+
+    /// The files of the torrent in their index order, whichever way the info dictionary lists them.
+    ///
+    /// The v1 keys are preferred, falling back to the v2 **file tree** for pure v2 torrents.
+    /// Paths are relative to the torrent root; for a single file torrent, the path is its name.
+    pub fn file_entries(&self) -> Vec<FileEntry<'_>> {
+        if let Some(files) = &self.files {
+            files
+                .iter()
+                .map(|file| FileEntry {
+                    path: file.path.iter().map(String::as_str).collect(),
+                    length: file.length,
+                    md5sum: file.md5sum.as_deref(),
+                })
+                .collect()
+        } else if let Some(length) = self.length {
+            vec![FileEntry {
+                path: vec![&self.name],
+                length: length.max(0) as u64,
+                md5sum: self.md5sum.as_deref(),
+            }]
+        } else {
+            self.file_tree_files()
+                .into_iter()
+                .map(|(path, file)| FileEntry {
+                    path,
+                    length: file.length,
+                    md5sum: None,
+                })
+                .collect()
+        }
+    }
+
+    /// The total size of the content in bytes, or `None` if the lengths add up to more than
+    /// a `u64` holds, which only a crafted torrent does.
+    pub fn total_size(&self) -> Option<u64> {
+        self.file_entries()
+            .iter()
+            .try_fold(0u64, |total, file| total.checked_add(file.length))
+    }
+
+    /// The number of files in the torrent.
+    pub fn file_count(&self) -> usize {
+        self.file_entries().len()
+    }
+}
+
+/// The BitTorrent protocol versions a torrent can be downloaded with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Version {
+    V1,
+    V2,
+    /// Both v1 and v2, with the v1 keys and the v2 **file tree** describing the same files.
+    Hybrid,
+    /// v1 keys with a BEP 30 **root hash**, a deprecated extension unrelated to v2 that few
+    /// clients ever supported.
+    Merkle,
+}
+
+/// A file of the torrent, as listed by either the v1 or the v2 keys.
+#[derive(Debug)]
+pub(crate) struct FileEntry<'a> {
+    pub path: Vec<&'a str>,
+    pub length: u64,
+    pub md5sum: Option<&'a str>,
+}
+
+/// **announce**, which a handful of nonstandard torrents make a list rather than a single URL.
+///
+/// The first URL of a list is taken as **announce**, and the rest are treated as a tier of
+/// their own after it. Empty URLs in a list are skipped. Either way it is serialized as just
+/// the one URL.
+#[derive(Debug, Clone, Serialize)]
+#[serde(into = "String")]
+pub(crate) struct Announce {
+    pub url: String,
+    pub rest: Vec<String>,
+    /// Whether this was encoded as a list.
+    pub is_list: bool,
+}
+
+impl Announce {
+    pub fn urls(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.url).chain(&self.rest)
+    }
+}
+
+impl From<Announce> for String {
+    fn from(announce: Announce) -> Self {
+        announce.url
+    }
+}
+
+impl<'de> Deserialize<'de> for Announce {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AnnounceVisitor;
+
+        impl<'de> Visitor<'de> for AnnounceVisitor {
+            type Value = Announce;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a tracker URL or a list of them")
+            }
+
+            fn visit_str<E: de::Error>(self, url: &str) -> Result<Announce, E> {
+                Ok(Announce {
+                    url: url.to_string(),
+                    rest: vec![],
+                    is_list: false,
+                })
+            }
+
+            fn visit_bytes<E: de::Error>(self, url: &[u8]) -> Result<Announce, E> {
+                let url = std::str::from_utf8(url)
+                    .map_err(|_| de::Error::invalid_value(de::Unexpected::Bytes(url), &self))?;
+                self.visit_str(url)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Announce, A::Error> {
+                let mut urls = Vec::new();
+                while let Some(tracker) = seq.next_element::<String>()? {
+                    if !tracker.is_empty() {
+                        urls.push(tracker);
+                    }
+                }
+                let url = match urls.is_empty() {
+                    true => String::new(),
+                    false => urls.remove(0),
+                };
+                Ok(Announce {
+                    url,
+                    rest: urls,
+                    is_list: true,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(AnnounceVisitor)
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub(crate) struct Node(pub String, pub i64);
 
+/// Nodes are deserialized as a sequence rather than a tuple: `serde_bencode` does not consume
+/// the end of a list read as a tuple, which breaks parsing whenever there is more than one node.
+impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NodeVisitor;
+
+        impl<'de> Visitor<'de> for NodeVisitor {
+            type Value = Node;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a list of host and port")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Node, A::Error> {
+                let host = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let port = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                while seq.next_element::<de::IgnoredAny>()?.is_some() {}
+                Ok(Node(host, port))
+            }
+        }
+
+        deserializer.deserialize_seq(NodeVisitor)
+    }
+}
+
+/// What kind of address the host of a DHT node is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum AddressType {
+    Ipv4,
+    Ipv6,
+    Hostname,
+}
+
+impl Node {
+    /// Classifies the host, or returns `None` if it is neither an IP address nor a plausible hostname.
+    ///
+    /// IPv6 addresses may be wrapped in brackets, as they are in URLs.
+    pub fn address_type(&self) -> Option<AddressType> {
+        let host = self.0.as_str();
+        let unbracketed = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+        if unbracketed.parse::<Ipv6Addr>().is_ok() {
+            Some(AddressType::Ipv6)
+        } else if host.parse::<Ipv4Addr>().is_ok() {
+            Some(AddressType::Ipv4)
+        } else if is_hostname(host) {
+            Some(AddressType::Hostname)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the port is one a node could listen on.
+    pub fn has_valid_port(&self) -> bool {
+        (1..=65535).contains(&self.1)
+    }
+}
+
+/// Checks a hostname against RFC 1123, also rejecting an all-numeric last label
+/// so that malformed IPv4 addresses like `1.2.3.999` do not pass as hostnames.
+fn is_hostname(host: &str) -> bool {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    if host.is_empty() || host.len() > 253 {
+        return false;
+    }
+    let labels_valid = host.split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+    });
+    let last = host.rsplit('.').next().unwrap_or_default();
+    labels_valid && !last.bytes().all(|b| b.is_ascii_digit())
+}
+
+thread_local! {
+    /// The most entries of **files** decoded, and how many were skipped past that, see
+    /// [`with_file_cap`].
+    static FILE_CAP: Cell<(usize, usize)> = const { Cell::new((usize::MAX, 0)) };
+}
+
+/// Runs `decode` with at most `cap` entries of **files** decoded, returning its result and how
+/// many entries were skipped.
+///
+/// The entries past the cap are still read through, so that the rest of the torrent can be,
+/// but are never decoded into memory.
+pub(crate) fn with_file_cap<T>(cap: usize, decode: impl FnOnce() -> T) -> (T, usize) {
+    let previous = FILE_CAP.with(|state| state.replace((cap, 0)));
+    let decoded = decode();
+    let (_, skipped) = FILE_CAP.with(|state| state.replace(previous));
+    (decoded, skipped)
+}
+
+/// The entries of **files** up to the cap of [`with_file_cap`].
+struct CappedFiles(Vec<File>);
+
+impl<'de> Deserialize<'de> for CappedFiles {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CappedFilesVisitor;
+
+        impl<'de> Visitor<'de> for CappedFilesVisitor {
+            type Value = CappedFiles;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a list of files")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<CappedFiles, A::Error> {
+                let (cap, _) = FILE_CAP.with(Cell::get);
+                let mut files = Vec::new();
+                while files.len() < cap {
+                    match seq.next_element()? {
+                        Some(file) => files.push(file),
+                        None => return Ok(CappedFiles(files)),
+                    }
+                }
+                let mut skipped = 0;
+                while seq.next_element::<IgnoredAny>()?.is_some() {
+                    skipped += 1;
+                }
+                FILE_CAP.with(|state| state.set((cap, skipped)));
+                Ok(CappedFiles(files))
+            }
+        }
+
+        deserializer.deserialize_seq(CappedFilesVisitor)
+    }
+}
+
+/// Reads **files**, giving every file without a path the placeholder `file-<index>`, so that
+/// the rest of the torrent can still be inspected.
+fn files<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<File>>, D::Error> {
+    let Some(CappedFiles(mut files)) = Option::<CappedFiles>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    for (index, file) in files.iter_mut().enumerate() {
+        if file.path.is_empty() {
+            file.path = vec![format!("file-{index}")];
+            file.path_missing = true;
+        }
+    }
+    Ok(Some(files))
+}
+
 /// Each file maps to dictionaries containing two keys :
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct File {
     /// The length of the file, in bytes.
     pub length: u64,
+    /// A 32-character hexadecimal string corresponding to the MD5 sum of the file.
     #[serde(default)]
     pub md5sum: Option<String>,
     /// A list of UTF-9 encoded strings corresponding to subdirectory names, the last
     /// of which is the actual file name (a zero length list is an error case).
+    ///
+    /// When it is missing or empty, a placeholder is put in its place, see [`File::path_missing`].
+    #[serde(default)]
     pub path: Vec<String>,
+    /// Whether **path** was missing or empty, and is made up of a placeholder instead.
+    #[serde(skip)]
+    pub path_missing: bool,
+    /// A download priority for the file.
+    ///
+    /// This is not part of any BEP, but some private trackers embed it to annotate their torrents.
+    /// When the file dictionary has none, it is taken from **file-priority** of the info dictionary.
+    #[serde(default)]
+    #[serde(alias = "file-priority", alias = "file_priority")]
+    pub priority: Option<i64>,
+
+    /// Any other keys of the file dictionary.
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+impl File {
+    /// Whether this is a padding file, which BEP 47 marks with a `p` in **attr**.
+    ///
+    /// Hybrid torrents insert these into **files** to align each real file to a piece boundary.
+    pub fn is_padding(&self) -> bool {
+        matches!(self.extra.get("attr"), Some(ExtraValue::Bytes(attr)) if attr.contains(&b'p'))
+    }
+}
+
+/// A node of the v2 **file tree**: either a file, or a directory of further nodes.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum FileTreeNode {
+    File(FileTreeLeaf),
+    Directory(BTreeMap<String, FileTreeNode>),
+}
+
+/// Files are keyed by the empty string inside the dictionary named after them.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct FileTreeLeaf {
+    #[serde(rename = "")]
+    pub file: FileTreeFile,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct FileTreeFile {
+    /// Length of the file in bytes.
+    pub length: u64,
+    /// For non-empty files this is the root hash of a merkle tree with a branching factor of 2,
+    /// constructed from 16KiB blocks of the file. The last block may be shorter than 16KiB.
+    #[serde(default, with = "serde_bytes")]
+    #[serde(rename = "pieces root")]
+    pub pieces_root: Option<Vec<u8>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{HashSelection, InfoHashes};
+
+    /// A pure v2 torrent of `hello.txt`, 50000 bytes in 16 KiB pieces, with no v1 keys at all.
+    const V2_SINGLE_FILE: &[u8] = include_bytes!("../tests/fixtures/v2_single_file.torrent");
+
+    #[test]
+    fn v2_single_file_size_comes_from_file_tree() {
+        let torrent: Torrent = serde_bencode::from_bytes(V2_SINGLE_FILE).unwrap();
+        assert_eq!(torrent.info.length, None);
+        assert!(torrent.info.files.is_none());
+        assert_eq!(torrent.info.total_size(), Some(50000));
+        assert_eq!(torrent.info.file_count(), 1);
+        let files = torrent.info.file_entries();
+        assert_eq!(files[0].path, ["hello.txt"]);
+        assert_eq!(files[0].length, 50000);
+    }
+
+    /// A pure v2 torrent of three files, `cover.jpg`, `disc 1/01.flac` and `disc 1/02.flac`,
+    /// in 32 KiB pieces, with **piece layers** for the two files longer than a piece.
+    const V2_MULTI_FILE: &[u8] = include_bytes!("../tests/fixtures/v2_multi_file.torrent");
+
+    #[test]
+    fn v2_torrent_has_meta_version() {
+        let torrent: Torrent = serde_bencode::from_bytes(V2_MULTI_FILE).unwrap();
+        let info = &torrent.info;
+        assert_eq!(info.meta_version, Some(2));
+        assert!(info.has_v2());
+        assert!(!info.has_v1());
+        assert_eq!(info.version(), Some(Version::V2));
+        assert_eq!(torrent.piece_layers.len(), 2);
+        let files: Vec<_> = info
+            .file_tree_files()
+            .into_iter()
+            .map(|(path, file)| (path.join("/"), file.length))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("cover.jpg".to_string(), 40000),
+                ("disc 1/01.flac".to_string(), 70000),
+                ("disc 1/02.flac".to_string(), 5000),
+            ]
+        );
+
+        // The SHA-256 of the info dictionary as written, **meta version** included.
+        let hashes = InfoHashes::compute(V2_MULTI_FILE, info, HashSelection::Both);
+        assert_eq!(hashes.v1, None);
+        assert_eq!(
+            hashes.v2.as_deref(),
+            Some("d1785f381f6987ce9839b107a5e638ce557e8aec0dd9967567ff396def2e3742")
+        );
+    }
+
+    #[test]
+    fn file_tree_without_meta_version_is_not_v2() {
+        let data = b"d4:infod9:file treed1:ad0:d6:lengthi1eeee4:name1:a12:piece lengthi16384eee";
+        let torrent: Torrent = serde_bencode::from_bytes(data).unwrap();
+        assert_eq!(torrent.info.meta_version, None);
+        assert!(!torrent.info.has_v2());
+        assert_eq!(torrent.info.version(), None);
+    }
+
+    #[test]
+    fn announce_without_urls_is_left_out() {
+        let info = "4:infod6:lengthi1e4:name1:a12:piece lengthi16384e6:pieces0:e";
+        for announce in ["8:announcele", "8:announcel0:0:e", "8:announce0:"] {
+            let data = format!("d{announce}{info}e");
+            let torrent: Torrent = serde_bencode::from_bytes(data.as_bytes()).unwrap();
+            assert!(torrent.announce.is_none(), "{announce}");
+            assert!(torrent.tiers().is_empty());
+            assert!(torrent.trackers().is_empty());
+        }
+
+        let data = format!("d8:announcel0:17:udp://t.example:1e{info}e");
+        let torrent: Torrent = serde_bencode::from_bytes(data.as_bytes()).unwrap();
+        assert_eq!(torrent.tiers(), [["udp://t.example:1"]]);
+    }
+
+    #[test]
+    fn total_size_overflow_is_none() {
+        let max = i64::MAX;
+        let data = format!(
+            "d4:infod5:filesld6:lengthi{max}e4:pathl1:aeed6:lengthi{max}e4:pathl1:bee\
+             d6:lengthi{max}e4:pathl1:ceee4:name1:x12:piece lengthi16384e6:pieces0:ee"
+        );
+        let torrent: Torrent = serde_bencode::from_bytes(data.as_bytes()).unwrap();
+        assert_eq!(torrent.info.file_count(), 3);
+        assert_eq!(torrent.info.total_size(), None);
+    }
 }
@@ -0,0 +1,109 @@
+use crate::torrent::Torrent;
+use serde::Serialize;
+use std::collections::HashSet;
+use url::Url;
+
+/// The tracker-related keys of a metainfo file, ready to be written back into a torrent.
+#[derive(Debug, Serialize)]
+pub(crate) struct TrackerList {
+    pub announce: Option<String>,
+    #[serde(rename = "announce-list")]
+    pub announce_list: Vec<Vec<String>>,
+}
+
+impl TrackerList {
+    /// Merges the trackers of several torrents.
+    ///
+    /// The tiers of each torrent are kept in order, one torrent after another,
+    /// so trackers that never shared a swarm do not end up in the same tier.
+    /// A tracker that already appeared earlier is dropped, as are tiers left empty by that.
+    pub fn merge<'a>(torrents: impl IntoIterator<Item = &'a Torrent>) -> Self {
+        let mut seen = HashSet::new();
+        let announce_list: Vec<Vec<String>> = torrents
+            .into_iter()
+            .flat_map(Torrent::tiers)
+            .map(|tier| {
+                tier.into_iter()
+                    .filter(|tracker| seen.insert(tracker.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|tier| !tier.is_empty())
+            .collect();
+
+        TrackerList {
+            announce: announce_list.first().map(|tier| tier[0].clone()),
+            announce_list,
+        }
+    }
+}
+
+/// What a tier of trackers is for, following BEP 12.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TierRole {
+    /// The first tier, which clients announce to first.
+    Primary,
+    /// A later tier, only announced to once every tier before it has failed.
+    Backup,
+}
+
+/// A tier of the trackers a client would use, with its place in the failover order.
+#[derive(Debug, Serialize)]
+pub(crate) struct Tier {
+    pub tier: usize,
+    pub role: TierRole,
+    pub trackers: Vec<String>,
+}
+
+impl Tier {
+    /// Annotates each of [`Torrent::tiers`] with its index and role.
+    pub fn list(torrent: &Torrent) -> Vec<Tier> {
+        torrent
+            .tiers()
+            .into_iter()
+            .enumerate()
+            .map(|(tier, trackers)| Tier {
+                tier,
+                role: match tier {
+                    0 => TierRole::Primary,
+                    _ => TierRole::Backup,
+                },
+                trackers,
+            })
+            .collect()
+    }
+}
+
+/// How many distinct trackers of a torrent announce over each scheme.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct TrackerStats {
+    pub http: usize,
+    pub https: usize,
+    pub udp: usize,
+    /// WebTorrent trackers, over either `ws` or `wss`.
+    pub websocket: usize,
+    /// Valid URLs of some other scheme.
+    pub other: usize,
+    pub unparseable: usize,
+}
+
+impl TrackerStats {
+    /// Counts the trackers across both **announce** and **announce-list**.
+    pub fn new(torrent: &Torrent) -> Self {
+        let mut stats = TrackerStats::default();
+        for tracker in torrent.trackers() {
+            let count = match Url::parse(tracker) {
+                Ok(url) if url.has_host() => match url.scheme() {
+                    "http" => &mut stats.http,
+                    "https" => &mut stats.https,
+                    "udp" => &mut stats.udp,
+                    "ws" | "wss" => &mut stats.websocket,
+                    _ => &mut stats.other,
+                },
+                _ => &mut stats.unparseable,
+            };
+            *count += 1;
+        }
+        stats
+    }
+}
@@ -0,0 +1,635 @@
+use crate::bencode;
+use crate::pieces::{self, PieceLengthRecommendation};
+use crate::torrent::{Info, Torrent, Version};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Query parameters of `/validate`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct ValidateOptions {
+    /// Also check the raw bencode for encodings that are lenient rather than strictly valid.
+    pub strict: bool,
+}
+
+/// Problems found in a torrent that did not prevent it from being parsed.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub(crate) enum Warning {
+    /// The file is not a bencoded metainfo dictionary at all.
+    Malformed { message: String },
+    /// A path segment is empty, `.` or `..`, or would make the path absolute, or the path has no
+    /// segments at all.
+    ///
+    /// Clients joining such a path onto their download directory may write outside of it,
+    /// so this indicates either a broken or a malicious torrent.
+    IllegalPath { path: String, segment: String },
+    /// A file of **files** has no **path**, or an empty one, so `placeholder` is shown instead.
+    MissingPath { index: usize, placeholder: String },
+    /// **pieces** is not made of whole 20-byte SHA-1 hashes.
+    InvalidPieces { remainder: usize },
+    /// **root hash** is not a 20-byte SHA-1 hash.
+    MalformedRootHash { root_hash: String },
+    /// **root hash** makes this a BEP 30 merkle torrent. That extension is deprecated, unrelated to
+    /// the merkle trees of v2, and was never supported by most clients.
+    MerkleTorrent,
+    /// A layer of **piece layers** is not made of whole 32-byte SHA-256 hashes.
+    InvalidPieceLayer {
+        pieces_root: String,
+        remainder: usize,
+    },
+    /// The v1 and v2 keys of a hybrid torrent describe different files.
+    ///
+    /// Only the first divergence is reported, as everything after it is usually shifted.
+    /// `v1` or `v2` is missing when the other list has more files.
+    HybridMismatch {
+        index: usize,
+        v1: Option<FileSummary>,
+        v2: Option<FileSummary>,
+    },
+    /// The v1 content, **length** of a single file or the sum of **files**, is not a size its
+    /// number of pieces can cover.
+    ///
+    /// With `n` pieces the content must be longer than `n - 1` pieces but no longer than `n`.
+    /// `min` and `max` are that range, and `difference` is how far `length` falls outside it,
+    /// each saturated at the bounds of its type.
+    LengthMismatch {
+        length: i64,
+        min: u64,
+        max: u64,
+        difference: i64,
+    },
+    /// **length** is negative.
+    NegativeLength { length: i64 },
+    /// A file is larger than [`MAX_PLAUSIBLE_LENGTH`], far beyond anything that is shared.
+    ImplausibleLength { path: String, length: u64 },
+    /// The file lengths add up to more than 2^64 - 1 bytes, so the torrent has no total size.
+    SizeOverflow,
+    /// **files** had more than `MAX_FILES` entries, and only the first `kept` are shown.
+    FilesTruncated { kept: usize, omitted: usize },
+    /// An integer has leading zeros or is negative zero, which strict bencode forbids.
+    ///
+    /// `offset` is where it starts in the file. Only looked for with `?strict=true`.
+    NonCanonicalInteger { offset: usize, literal: String },
+    /// `count` bytes follow the end of the metainfo dictionary and were ignored, which points to
+    /// a corrupted download, like HTTP chunking artifacts or files concatenated together.
+    TrailingBytes { count: usize },
+    /// Both **length** and **files** are present, so it is unclear whether this is a single file torrent.
+    AmbiguousLayout,
+    /// Neither **length**, **files** nor a v2 **file tree** is present.
+    MissingLayout,
+    /// **meta version** is 2, but there is no **file tree** to go with it.
+    MissingFileTree,
+    /// There is a **file tree**, but **meta version** is not 2, so clients ignore it.
+    IgnoredFileTree { meta_version: Option<u8> },
+    /// A top-level string such as **comment** was not valid UTF-8, and has been decoded with
+    /// replacement characters in place of the invalid bytes.
+    LossyString { field: &'static str },
+    /// **encoding** names something other than UTF-8, which is what every string is read as.
+    NonUtf8Encoding { encoding: String },
+    /// An **md5sum** is not a 32-character hexadecimal string.
+    MalformedMd5sum { path: String, md5sum: String },
+    /// **piece length** is not a power of two, which nearly every client assumes and v2 requires.
+    PieceLengthNotPowerOfTwo { piece_length: i64 },
+    /// **piece length** is far from what suits the size of the content.
+    UnusualPieceLength { piece_length: i64, recommended: u64 },
+    /// A DHT node is neither an IP address nor a plausible hostname, or has an out of range port.
+    InvalidNode { host: String, port: i64 },
+    /// **announce** is a list of `trackers` rather than a single URL.
+    ///
+    /// The first is read as **announce** and the others as a tier after it, but most clients
+    /// would fail to load this torrent at all.
+    ListAnnounce { trackers: usize },
+    /// A tier of **announce-list** has no trackers in it, which some clients choke on.
+    EmptyTier { tier: usize },
+    /// A tracker of **announce-list** is an empty string.
+    EmptyTrackerUrl { tier: usize },
+    /// A tracker is not a valid absolute URL.
+    InvalidTrackerUrl { url: String },
+    /// A tracker is not on the configured allowlist of hostnames; `host` is `None` if it has none.
+    DisallowedTracker { url: String, host: Option<String> },
+    /// A tracker uses a scheme no BitTorrent client announces over.
+    UnknownTrackerScheme { url: String, scheme: String },
+}
+
+impl Warning {
+    /// Whether this problem makes the torrent invalid, rather than just suspicious.
+    pub fn is_error(&self) -> bool {
+        match self {
+            Warning::Malformed { .. }
+            | Warning::IllegalPath { .. }
+            | Warning::MissingPath { .. }
+            | Warning::InvalidPieces { .. }
+            | Warning::MalformedRootHash { .. }
+            | Warning::InvalidPieceLayer { .. }
+            | Warning::HybridMismatch { .. }
+            | Warning::AmbiguousLayout
+            | Warning::MissingLayout
+            | Warning::MissingFileTree
+            | Warning::DisallowedTracker { .. }
+            | Warning::NegativeLength { .. }
+            | Warning::SizeOverflow
+            | Warning::NonCanonicalInteger { .. } => true,
+            Warning::IgnoredFileTree { .. }
+            | Warning::MerkleTorrent
+            | Warning::LengthMismatch { .. }
+            | Warning::ImplausibleLength { .. }
+            | Warning::FilesTruncated { .. }
+            | Warning::TrailingBytes { .. }
+            | Warning::LossyString { .. }
+            | Warning::NonUtf8Encoding { .. }
+            | Warning::MalformedMd5sum { .. }
+            | Warning::PieceLengthNotPowerOfTwo { .. }
+            | Warning::UnusualPieceLength { .. }
+            | Warning::InvalidNode { .. }
+            | Warning::ListAnnounce { .. }
+            | Warning::EmptyTier { .. }
+            | Warning::EmptyTrackerUrl { .. }
+            | Warning::InvalidTrackerUrl { .. }
+            | Warning::UnknownTrackerScheme { .. } => false,
+        }
+    }
+}
+
+/// A file as either version of a hybrid torrent lists it.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct FileSummary {
+    pub path: String,
+    pub length: u64,
+}
+
+/// The outcome of validating a torrent, without the torrent itself.
+#[derive(Debug, Serialize)]
+pub(crate) struct Verdict {
+    pub valid: bool,
+    pub warnings: Vec<Warning>,
+    pub errors: Vec<Warning>,
+}
+
+impl Verdict {
+    pub fn new(problems: Vec<Warning>) -> Self {
+        let (errors, warnings): (Vec<_>, Vec<_>) =
+            problems.into_iter().partition(Warning::is_error);
+        Verdict {
+            valid: errors.is_empty(),
+            warnings,
+            errors,
+        }
+    }
+}
+
+/// The largest file length that is not suspicious, 1 PiB.
+pub(crate) const MAX_PLAUSIBLE_LENGTH: u64 = 1 << 50;
+
+/// Checks that nothing follows the metainfo dictionary in the raw torrent.
+pub(crate) fn check_trailing(data_raw: &[u8]) -> Option<Warning> {
+    bencode::trailing_bytes(data_raw)
+        .filter(|count| *count > 0)
+        .map(|count| Warning::TrailingBytes { count })
+}
+
+/// Checks that every integer of the raw torrent is canonically encoded.
+pub(crate) fn check_integers(data_raw: &[u8]) -> Vec<Warning> {
+    bencode::noncanonical_integers(data_raw)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(offset, literal)| Warning::NonCanonicalInteger { offset, literal })
+        .collect()
+}
+
+/// Runs every check against the parsed torrent.
+pub(crate) fn validate(torrent: &Torrent) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+
+    let info = &torrent.info;
+    check_path(&mut warnings, std::slice::from_ref(&info.name));
+    for (index, file) in info.files.iter().flatten().enumerate() {
+        if file.path_missing {
+            warnings.push(Warning::MissingPath {
+                index,
+                placeholder: file.path.join("/"),
+            });
+        } else {
+            check_path(&mut warnings, &file.path);
+        }
+    }
+    for (path, _) in info.file_tree_files() {
+        let path: Vec<String> = path.into_iter().map(String::from).collect();
+        check_path(&mut warnings, &path);
+    }
+
+    for (path, md5sum) in info.md5sums() {
+        if !is_md5sum(md5sum) {
+            warnings.push(Warning::MalformedMd5sum {
+                path,
+                md5sum: md5sum.to_string(),
+            });
+        }
+    }
+
+    if info.files_omitted > 0 {
+        warnings.push(Warning::FilesTruncated {
+            kept: info.files.as_ref().map_or(0, Vec::len),
+            omitted: info.files_omitted,
+        });
+    }
+
+    if let Some(root_hash) = &info.root_hash {
+        warnings.push(Warning::MerkleTorrent);
+        if root_hash.len() != 40 || !root_hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            warnings.push(Warning::MalformedRootHash {
+                root_hash: root_hash.clone(),
+            });
+        }
+    }
+
+    let remainder = info.pieces.len() % 20;
+    if remainder != 0 {
+        warnings.push(Warning::InvalidPieces { remainder });
+    } else if info.version() == Some(Version::Merkle) && info.pieces.is_empty() {
+        // The pieces are only known through **root hash**, so there is nothing to count.
+    } else {
+        let length = match (info.length, &info.files) {
+            (Some(length), None) if length >= 0 => Some(length),
+            (None, Some(files)) => Some(
+                files
+                    .iter()
+                    .map(|file| file.length)
+                    .fold(0u64, u64::saturating_add)
+                    .min(i64::MAX as u64) as i64,
+            ),
+            _ => None,
+        };
+        if let Some(length) = length {
+            check_length(
+                &mut warnings,
+                length,
+                info.pieces.len() / 20,
+                info.piece_length,
+            );
+        }
+    }
+    if let Some(length) = info.length.filter(|length| *length < 0) {
+        warnings.push(Warning::NegativeLength { length });
+    }
+    for file in info.file_entries() {
+        if file.length > MAX_PLAUSIBLE_LENGTH {
+            warnings.push(Warning::ImplausibleLength {
+                path: file.path.join("/"),
+                length: file.length,
+            });
+        }
+    }
+
+    for (pieces_root, layer) in &torrent.piece_layers {
+        let remainder = layer.len() % 32;
+        if remainder != 0 {
+            warnings.push(Warning::InvalidPieceLayer {
+                pieces_root: hex::encode(pieces_root),
+                remainder,
+            });
+        }
+    }
+
+    if info.piece_length > 0 && pieces::length_exponent(info.piece_length).is_none() {
+        warnings.push(Warning::PieceLengthNotPowerOfTwo {
+            piece_length: info.piece_length,
+        });
+    }
+    match info.total_size() {
+        Some(total_size) => {
+            let recommendation = PieceLengthRecommendation::new(total_size, info.piece_length);
+            if recommendation.is_far_off() {
+                warnings.push(Warning::UnusualPieceLength {
+                    piece_length: info.piece_length,
+                    recommended: recommendation.recommended,
+                });
+            }
+        }
+        None => warnings.push(Warning::SizeOverflow),
+    }
+
+    if info.version() == Some(Version::Hybrid) {
+        check_hybrid(&mut warnings, info);
+    }
+
+    match (&info.length, &info.files, &info.file_tree) {
+        (Some(_), Some(_), _) => warnings.push(Warning::AmbiguousLayout),
+        (None, None, None) => warnings.push(Warning::MissingLayout),
+        _ => {}
+    }
+    match (info.meta_version, &info.file_tree) {
+        (Some(2), None) => warnings.push(Warning::MissingFileTree),
+        (meta_version, Some(_)) if meta_version != Some(2) => {
+            warnings.push(Warning::IgnoredFileTree { meta_version })
+        }
+        _ => {}
+    }
+
+    for (field, value) in [
+        ("comment", &torrent.comment),
+        ("created by", &torrent.created_by),
+        ("encoding", &torrent.encoding),
+    ] {
+        if value
+            .as_ref()
+            .is_some_and(|value| value.contains('\u{FFFD}'))
+        {
+            warnings.push(Warning::LossyString { field });
+        }
+    }
+
+    if let Some(encoding) = &torrent.encoding {
+        if !encoding.eq_ignore_ascii_case("UTF-8") && !encoding.eq_ignore_ascii_case("UTF8") {
+            warnings.push(Warning::NonUtf8Encoding {
+                encoding: encoding.clone(),
+            });
+        }
+    }
+
+    for node in &torrent.nodes {
+        if node.address_type().is_none() || !node.has_valid_port() {
+            warnings.push(Warning::InvalidNode {
+                host: node.0.clone(),
+                port: node.1,
+            });
+        }
+    }
+
+    if let Some(announce) = torrent.announce.as_ref().filter(|a| a.is_list) {
+        warnings.push(Warning::ListAnnounce {
+            trackers: announce.urls().count(),
+        });
+    }
+
+    for (tier, trackers) in torrent.announce_list.iter().enumerate() {
+        if trackers.is_empty() {
+            warnings.push(Warning::EmptyTier { tier });
+        }
+        if trackers.iter().any(String::is_empty) {
+            warnings.push(Warning::EmptyTrackerUrl { tier });
+        }
+    }
+
+    // Empty URLs in announce-list have been reported above already.
+    for tracker in torrent.trackers().into_iter().filter(|t| !t.is_empty()) {
+        check_tracker(&mut warnings, tracker);
+    }
+
+    warnings
+}
+
+/// Flags every tracker whose host is not one of `allowed`, ignoring case.
+pub(crate) fn check_allowed_trackers(torrent: &Torrent, allowed: &[String]) -> Vec<Warning> {
+    torrent
+        .trackers()
+        .into_iter()
+        .filter(|tracker| !tracker.is_empty())
+        .filter_map(|tracker| {
+            let host = Url::parse(tracker)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_ascii_lowercase));
+            let is_allowed = host
+                .as_ref()
+                .is_some_and(|host| allowed.iter().any(|a| a.eq_ignore_ascii_case(host)));
+            (!is_allowed).then(|| Warning::DisallowedTracker {
+                url: tracker.to_string(),
+                host,
+            })
+        })
+        .collect()
+}
+
+fn check_path(warnings: &mut Vec<Warning>, path: &[String]) {
+    if path.is_empty() {
+        warnings.push(Warning::IllegalPath {
+            path: String::new(),
+            segment: String::new(),
+        });
+    }
+    for segment in path {
+        if !is_legal_segment(segment) {
+            warnings.push(Warning::IllegalPath {
+                path: path.join("/"),
+                segment: segment.clone(),
+            });
+        }
+    }
+}
+
+/// Whether `segment` names a file or directory inside of the directory it is joined onto.
+///
+/// Besides separators, this rules out a Windows drive prefix like `C:`, which makes `C:foo` a path
+/// relative to the current directory of drive `C` rather than to the download directory.
+pub(crate) fn is_legal_segment(segment: &str) -> bool {
+    let drive = matches!(segment.as_bytes(), [letter, b':', ..] if letter.is_ascii_alphabetic());
+    !segment.is_empty()
+        && segment != "."
+        && segment != ".."
+        && !segment.contains(['/', '\\'])
+        && !drive
+}
+
+fn check_length(warnings: &mut Vec<Warning>, length: i64, pieces: usize, piece_length: i64) {
+    if piece_length < 0 {
+        return;
+    }
+    // Wide enough that neither bound overflows, whatever the piece count and length.
+    let (length, pieces, piece_length) =
+        (i128::from(length), pieces as i128, i128::from(piece_length));
+    let min = if pieces > 0 {
+        (pieces - 1) * piece_length + 1
+    } else {
+        0
+    };
+    let max = pieces * piece_length;
+    let difference = if length < min {
+        length - min
+    } else if length > max {
+        length - max
+    } else {
+        return;
+    };
+    let saturate_u64 = |value: i128| u64::try_from(value).unwrap_or(u64::MAX);
+    let saturate_i64 = |value: i128| value.clamp(i64::MIN.into(), i64::MAX.into()) as i64;
+    warnings.push(Warning::LengthMismatch {
+        length: saturate_i64(length),
+        min: saturate_u64(min),
+        max: saturate_u64(max),
+        difference: saturate_i64(difference),
+    });
+}
+
+fn check_hybrid(warnings: &mut Vec<Warning>, info: &Info) {
+    let v1: Vec<FileSummary> = match (&info.files, info.length) {
+        (Some(files), _) => files
+            .iter()
+            .filter(|file| !file.is_padding())
+            .map(|file| FileSummary {
+                path: file.path.join("/"),
+                length: file.length,
+            })
+            .collect(),
+        (None, length) => vec![FileSummary {
+            path: info.name.clone(),
+            length: length.unwrap_or_default().max(0) as u64,
+        }],
+    };
+    let mut v1 = v1.into_iter();
+    let mut v2 = info
+        .file_tree_files()
+        .into_iter()
+        .map(|(path, file)| FileSummary {
+            path: path.join("/"),
+            length: file.length,
+        });
+
+    for index in 0.. {
+        match (v1.next(), v2.next()) {
+            (None, None) => return,
+            (v1, v2) if v1 == v2 => {}
+            (v1, v2) => {
+                warnings.push(Warning::HybridMismatch { index, v1, v2 });
+                return;
+            }
+        }
+    }
+}
+
+pub(crate) fn is_md5sum(md5sum: &str) -> bool {
+    md5sum.len() == 32 && md5sum.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn check_tracker(warnings: &mut Vec<Warning>, tracker: &str) {
+    match Url::parse(tracker) {
+        Ok(url) if url.has_host() => {
+            if !matches!(url.scheme(), "http" | "https" | "udp" | "ws" | "wss") {
+                warnings.push(Warning::UnknownTrackerScheme {
+                    url: tracker.to_string(),
+                    scheme: url.scheme().to_string(),
+                });
+            }
+        }
+        _ => warnings.push(Warning::InvalidTrackerUrl {
+            url: tracker.to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inspection::{InspectOptions, Inspection};
+
+    fn torrent(data: &[u8]) -> Torrent {
+        serde_bencode::from_bytes(data).unwrap()
+    }
+
+    #[test]
+    fn truncated_pieces_are_reported_but_still_inspectable() {
+        let mut data = b"d8:announce18:http://t.example/a4:infod6:lengthi40000e4:name1:x\
+                         12:piece lengthi16384e6:pieces41:"
+            .to_vec();
+        data.extend([0xab; 41]);
+        data.extend(b"ee");
+
+        let warnings = validate(&torrent(&data));
+        assert!(warnings
+            .iter()
+            .any(|w| matches!(w, Warning::InvalidPieces { remainder: 1 })));
+
+        let inspection = Inspection::new(torrent(&data), &data, &InspectOptions::default());
+        assert_eq!(inspection.torrent.info.name, "x");
+        assert_eq!(inspection.trackers, ["http://t.example/a"]);
+        assert_eq!(inspection.total_size, Some(40000));
+        assert_eq!(inspection.piece_count, 2);
+    }
+
+    #[test]
+    fn invalid_utf8_comment_is_decoded_lossily() {
+        let mut data = b"d7:comment4:ok".to_vec();
+        data.extend([0xff, 0xfe]);
+        data.extend(b"4:infod6:lengthi1e4:name1:x12:piece lengthi16384e6:pieces20:");
+        data.extend([0xab; 20]);
+        data.extend(b"ee");
+
+        let torrent = torrent(&data);
+        assert_eq!(torrent.comment.as_deref(), Some("ok\u{FFFD}\u{FFFD}"));
+        let warnings = validate(&torrent);
+        assert!(warnings
+            .iter()
+            .any(|w| matches!(w, Warning::LossyString { field: "comment" })));
+    }
+
+    #[test]
+    fn file_without_path_gets_a_placeholder() {
+        let mut data = b"d4:infod5:filesld6:lengthi1e4:pathl1:aeed6:lengthi1eee\
+                         4:name1:x12:piece lengthi16384e6:pieces20:"
+            .to_vec();
+        data.extend([0xab; 20]);
+        data.extend(b"ee");
+
+        let torrent = torrent(&data);
+        let files = torrent.info.files.as_ref().unwrap();
+        assert_eq!(files[0].path, ["a"]);
+        assert!(!files[0].path_missing);
+        assert_eq!(files[1].path, ["file-1"]);
+        assert!(files[1].path_missing);
+
+        let warnings = validate(&torrent);
+        assert!(warnings.iter().any(|w| matches!(
+            w,
+            Warning::MissingPath { index: 1, placeholder } if placeholder == "file-1"
+        )));
+    }
+
+    #[test]
+    fn path_segments_that_leave_the_directory_are_illegal() {
+        let illegal = |path: &[&str]| {
+            let path: Vec<String> = path.iter().map(|s| s.to_string()).collect();
+            let mut warnings = Vec::new();
+            check_path(&mut warnings, &path);
+            warnings
+                .into_iter()
+                .map(|w| match w {
+                    Warning::IllegalPath { segment, .. } => segment,
+                    w => panic!("unexpected {w:?}"),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert!(illegal(&["a", "b.txt"]).is_empty());
+        assert!(illegal(&["ab:c", "..."]).is_empty());
+        assert_eq!(illegal(&["a", "..", "b"]), [".."]);
+        assert_eq!(illegal(&[".", "b"]), ["."]);
+        assert_eq!(illegal(&["a/b", "c\\d"]), ["a/b", "c\\d"]);
+        assert_eq!(illegal(&["a", "", "b"]), [""]);
+        assert_eq!(illegal(&["C:"]), ["C:"]);
+        assert_eq!(illegal(&["c:foo", "b"]), ["c:foo"]);
+        assert_eq!(illegal(&[]), [""]);
+    }
+
+    #[test]
+    fn huge_piece_length_does_not_overflow_the_length_check() {
+        let mut data = format!(
+            "d4:infod6:lengthi{}e4:name1:x12:piece lengthi{}e6:pieces80:",
+            i64::MAX,
+            i64::MAX
+        )
+        .into_bytes();
+        data.extend([0xab; 80]);
+        data.extend(b"ee");
+
+        let warnings = validate(&torrent(&data));
+        let mismatch = warnings.iter().find_map(|w| match w {
+            Warning::LengthMismatch {
+                min,
+                max,
+                difference,
+                ..
+            } => Some((*min, *max, *difference)),
+            _ => None,
+        });
+        // 4 pieces need more than 3 * i64::MAX bytes, far beyond the length.
+        assert_eq!(mismatch, Some((u64::MAX, u64::MAX, i64::MIN)));
+    }
+}
@@ -0,0 +1,359 @@
+//! Checking content on disk against the v1 piece hashes of a saved torrent, with the progress
+//! streamed as Server-Sent Events.
+//!
+//! The content is looked for in `CONTENT_DIR`, laid out the way clients download it: `<name>`
+//! for a single file torrent, and `<name>/<path>` for each file of any other. Hashing runs on
+//! the blocking thread pool and reads at most [`CHUNK`] bytes at a time, whatever the piece
+//! length. A `progress` event is sent about every percent of the pieces, an `unreadable` event
+//! for each file that is missing or too short, and the stream ends with `done` and the totals.
+//! Hashing stops as soon as the client goes away.
+
+use crate::config::Config;
+use crate::error::{AppError, ErrorCode};
+use crate::saved_torrent;
+use crate::store::Store;
+use crate::torrent::Info;
+use crate::validate::is_legal_segment;
+use axum::extract::{Extension, Path};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use futures_util::stream;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::convert::Infallible;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// The most bytes read from disk at once.
+const CHUNK: usize = 64 * 1024;
+
+/// How many pieces were checked so far, and how many of them did not match.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+struct Progress {
+    verified: usize,
+    failed: usize,
+    total: usize,
+}
+
+/// Verifies the content of a saved torrent, sending the progress as it goes.
+pub(crate) async fn verify(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(store): Extension<Store>,
+    Path(info_hash): Path<String>,
+) -> Result<Response, AppError> {
+    let Some(content_dir) = config.content_dir.clone() else {
+        return Err(AppError::new(
+            ErrorCode::ContentUnavailable,
+            "Verification needs CONTENT_DIR to be set",
+        ));
+    };
+    let torrent = saved_torrent(&store, &config, &info_hash).await?;
+    if !torrent.info.has_v1() {
+        return Err(AppError::new(
+            ErrorCode::InvalidRequest,
+            format!("{info_hash} has no v1 pieces to verify"),
+        ));
+    }
+    let invalid = |message: &str| AppError::new(ErrorCode::InvalidMetainfo, message);
+    let piece_length = u64::try_from(torrent.info.piece_length)
+        .ok()
+        .filter(|length| *length > 0)
+        .ok_or_else(|| invalid("The piece length is not positive"))?;
+    let files = ContentFile::all(&torrent.info, content_dir)
+        .ok_or_else(|| invalid("A file path leads outside of the torrent's directory"))?;
+    let hashes: Vec<[u8; 20]> = torrent
+        .info
+        .piece_hashes()
+        .map(|hash| hash.try_into().unwrap())
+        .collect();
+
+    let (events, received) = mpsc::channel(64);
+    tokio::task::spawn_blocking(move || {
+        if let Some(totals) = check(&files, piece_length, &hashes, &events) {
+            let _ = events.blocking_send(event("done", &totals));
+        }
+    });
+
+    let events = stream::unfold(received, |mut received| async move {
+        let event = received.recv().await?;
+        Some((Ok::<_, Infallible>(event), received))
+    });
+    Ok(Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response())
+}
+
+/// A file of the torrent, where its content is expected on disk.
+#[derive(Debug)]
+struct ContentFile {
+    /// The path as the torrent lists it, for events.
+    name: String,
+    /// `None` for BEP 47 padding files, which are all zeros and never written to disk.
+    path: Option<PathBuf>,
+    length: u64,
+}
+
+impl ContentFile {
+    /// The files of `info` in piece order, or `None` if a path could lead outside of `dir`.
+    fn all(info: &Info, dir: PathBuf) -> Option<Vec<ContentFile>> {
+        if !is_legal_segment(&info.name) {
+            return None;
+        }
+        let root = dir.join(&info.name);
+        let Some(files) = &info.files else {
+            return Some(vec![ContentFile {
+                name: info.name.clone(),
+                path: Some(root),
+                length: info.length.unwrap_or_default().max(0) as u64,
+            }]);
+        };
+        files
+            .iter()
+            .map(|file| {
+                if !file.path.iter().all(|segment| is_legal_segment(segment)) {
+                    return None;
+                }
+                Some(ContentFile {
+                    name: file.path.join("/"),
+                    path: (!file.is_padding()).then(|| root.join(file.path.join("/"))),
+                    length: file.length,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A file that could not be read to the end.
+#[derive(Serialize)]
+struct Unreadable<'a> {
+    file: &'a str,
+    message: String,
+}
+
+/// Hashes the content piece by piece, sending events to `events`.
+///
+/// Returns the totals, or `None` if the client went away before the end.
+fn check(
+    files: &[ContentFile],
+    piece_length: u64,
+    hashes: &[[u8; 20]],
+    events: &mpsc::Sender<Event>,
+) -> Option<Progress> {
+    let mut progress = Progress {
+        total: hashes.len(),
+        ..Progress::default()
+    };
+    let every = hashes.len().div_ceil(100).max(1);
+    let mut content = Content::new(files);
+    for (index, expected) in hashes.iter().enumerate() {
+        let mut sha1 = Sha1::new();
+        let read = content.read(piece_length, &mut sha1, events)?;
+        if !(read && <[u8; 20]>::from(sha1.finalize()) == *expected) {
+            progress.failed += 1;
+        }
+        progress.verified += 1;
+        if (index + 1) % every == 0 || index + 1 == hashes.len() {
+            events.blocking_send(event("progress", &progress)).ok()?;
+        }
+    }
+    Some(progress)
+}
+
+/// Reads the files one after the other, as if they were concatenated.
+struct Content<'a> {
+    files: &'a [ContentFile],
+    /// The file being read, and how far into it.
+    index: usize,
+    offset: u64,
+    open: Option<File>,
+    /// Whether each file failed to read, so the rest of it is skipped.
+    unreadable: Vec<bool>,
+    buffer: Vec<u8>,
+}
+
+impl<'a> Content<'a> {
+    fn new(files: &'a [ContentFile]) -> Self {
+        Content {
+            files,
+            index: 0,
+            offset: 0,
+            open: None,
+            unreadable: vec![false; files.len()],
+            buffer: vec![0; CHUNK],
+        }
+    }
+
+    /// Feeds the next `length` bytes to `sha1`, or fewer at the end of the content.
+    ///
+    /// Returns whether all of them could be read, or `None` if the client went away while
+    /// being told about an unreadable file.
+    fn read(&mut self, length: u64, sha1: &mut Sha1, events: &mpsc::Sender<Event>) -> Option<bool> {
+        let mut remaining = length;
+        let mut complete = true;
+        while remaining > 0 && self.index < self.files.len() {
+            let file = &self.files[self.index];
+            let take = remaining.min(file.length - self.offset);
+            if self.unreadable[self.index] {
+                complete = false;
+            } else if let Err(error) = self.feed(take, sha1) {
+                self.unreadable[self.index] = true;
+                self.open = None;
+                complete = false;
+                let unreadable = Unreadable {
+                    file: &file.name,
+                    message: match error.kind() {
+                        io::ErrorKind::UnexpectedEof => "The file is too short".to_string(),
+                        _ => error.to_string(),
+                    },
+                };
+                events
+                    .blocking_send(event("unreadable", &unreadable))
+                    .ok()?;
+            }
+            remaining -= take;
+            self.offset += take;
+            if self.offset == file.length {
+                self.index += 1;
+                self.offset = 0;
+                self.open = None;
+            }
+        }
+        Some(complete)
+    }
+
+    /// Feeds the next `length` bytes of the current file to `sha1`.
+    fn feed(&mut self, mut length: u64, sha1: &mut Sha1) -> io::Result<()> {
+        let file = &self.files[self.index];
+        let Some(path) = &file.path else {
+            self.buffer.fill(0);
+            while length > 0 {
+                let take = length.min(CHUNK as u64) as usize;
+                sha1.update(&self.buffer[..take]);
+                length -= take as u64;
+            }
+            return Ok(());
+        };
+        let open = match &mut self.open {
+            Some(open) => open,
+            None => self.open.insert(File::open(path)?),
+        };
+        while length > 0 {
+            let take = length.min(CHUNK as u64) as usize;
+            open.read_exact(&mut self.buffer[..take])?;
+            sha1.update(&self.buffer[..take]);
+            length -= take as u64;
+        }
+        Ok(())
+    }
+}
+
+/// An event named `name`, with `data` as JSON.
+fn event(name: &str, data: &impl Serialize) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(data)
+        .unwrap_or_else(|_| Event::default().event(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `files` into a fresh directory, returning them as the torrent lists them.
+    fn write(test: &str, files: &[(&str, &[u8])]) -> Vec<ContentFile> {
+        let dir = std::env::temp_dir().join(format!(
+            "torrent-inspector-verify-{test}-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        files
+            .iter()
+            .map(|(name, data)| {
+                let path = dir.join(name);
+                std::fs::write(&path, data).unwrap();
+                ContentFile {
+                    name: name.to_string(),
+                    path: Some(path),
+                    length: data.len() as u64,
+                }
+            })
+            .collect()
+    }
+
+    fn hashes(content: &[u8], piece_length: usize) -> Vec<[u8; 20]> {
+        content
+            .chunks(piece_length)
+            .map(|piece| Sha1::digest(piece).into())
+            .collect()
+    }
+
+    #[test]
+    fn finds_corrupt_pieces_across_files() {
+        let content: Vec<u8> = (0..=255).cycle().take(100_000).collect();
+        let (a, b) = content.split_at(30_000);
+        let mut files = write("corrupt", &[("a", a), ("b", b)]);
+        // A padding file in the middle is read as zeros without touching the disk.
+        files.insert(
+            1,
+            ContentFile {
+                name: ".pad/1".to_string(),
+                path: None,
+                length: 2_768,
+            },
+        );
+        let mut padded = a.to_vec();
+        padded.extend([0; 2_768]);
+        padded.extend(b);
+        let mut hashes = hashes(&padded, 16_384);
+        hashes[3] = [0; 20];
+
+        let (events, _received) = mpsc::channel(1024);
+        let totals = check(&files, 16_384, &hashes, &events);
+        assert_eq!(
+            totals,
+            Some(Progress {
+                verified: 7,
+                failed: 1,
+                total: 7
+            })
+        );
+    }
+
+    #[test]
+    fn pieces_of_missing_files_fail() {
+        let mut files = write("missing", &[("a", &[1; 20_000])]);
+        files.push(ContentFile {
+            name: "b".to_string(),
+            path: Some(PathBuf::from("/nonexistent/b")),
+            length: 20_000,
+        });
+        let mut content = vec![1; 20_000];
+        content.extend([2; 20_000]);
+
+        let (events, _received) = mpsc::channel(1024);
+        let totals = check(&files, 16_384, &self::hashes(&content, 16_384), &events);
+        // The second piece straddles both files, so only the first one matches.
+        assert_eq!(
+            totals,
+            Some(Progress {
+                verified: 3,
+                failed: 2,
+                total: 3
+            })
+        );
+    }
+
+    #[test]
+    fn stops_once_the_client_is_gone() {
+        let files = write("gone", &[("a", &[1; 20_000])]);
+        let (events, received) = mpsc::channel(1024);
+        drop(received);
+        assert_eq!(
+            check(&files, 16_384, &hashes(&[1; 20_000], 16_384), &events),
+            None
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! Inspecting a torrent streamed over a WebSocket, with an event for each phase.
+//!
+//! The client sends the torrent as any number of binary messages, then the text message `end`.
+//! Every binary message is acknowledged with a `received` event. Once the torrent is complete,
+//! it is parsed and a `name`, `trackers`, `files` and `hashes` event follow, then `done` with the
+//! whole inspection. Any failure is sent as a `fail` event, after which the socket is closed.
+
+use crate::config::Config;
+use crate::decompress;
+use crate::error::{AppError, ErrorCode};
+use crate::hash::InfoHashes;
+use crate::inspection::{InspectOptions, Inspection};
+use crate::quota::Quota;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, Extension, Query};
+use axum::response::Response;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// A message sent to the client, as JSON text.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    /// The bytes received so far.
+    Received {
+        bytes: usize,
+    },
+    Name {
+        name: &'a str,
+    },
+    /// The distinct trackers across **announce** and **announce-list**.
+    Trackers {
+        count: usize,
+    },
+    Files {
+        count: usize,
+        total_size: Option<u64>,
+    },
+    Hashes {
+        info_hash: &'a InfoHashes,
+    },
+    Done {
+        inspection: &'a Inspection,
+    },
+    Fail {
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+pub(crate) async fn inspect(
+    upgrade: WebSocketUpgrade,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(quota): Extension<Arc<Quota>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(options): Query<InspectOptions>,
+) -> Response {
+    upgrade.on_upgrade(move |mut socket| async move {
+        if let Err(failure) = stream(&mut socket, &config, &quota, addr, options).await {
+            let fail = Event::Fail {
+                code: failure.code,
+                message: failure.message,
+            };
+            let _ = send(&mut socket, &fail).await;
+        }
+        let _ = socket.close().await;
+    })
+}
+
+async fn send(socket: &mut WebSocket, event: &Event<'_>) -> Result<(), AppError> {
+    let text = serde_json::to_string(event).unwrap_or_default();
+    socket
+        .send(Message::Text(text))
+        .await
+        .map_err(|_| AppError::new(ErrorCode::UploadUnreadable, "The connection was lost"))
+}
+
+async fn stream(
+    socket: &mut WebSocket,
+    config: &Config,
+    quota: &Quota,
+    addr: SocketAddr,
+    mut options: InspectOptions,
+) -> Result<(), AppError> {
+    let lost = || AppError::new(ErrorCode::UploadUnreadable, "The connection was lost");
+    let mut data = Vec::new();
+    loop {
+        match socket.recv().await.ok_or_else(lost)?.map_err(|_| lost())? {
+            Message::Binary(chunk) => {
+                if data.len() as u64 + chunk.len() as u64 > config.max_upload_bytes {
+                    return Err(AppError::new(
+                        ErrorCode::UploadTooLarge,
+                        format!("Upload exceeds {} bytes", config.max_upload_bytes),
+                    ));
+                }
+                if !quota.charge(addr.ip(), chunk.len() as u64) {
+                    return Err(AppError::new(
+                        ErrorCode::QuotaExceeded,
+                        "Daily upload quota exceeded",
+                    ));
+                }
+                data.extend_from_slice(&chunk);
+                send(socket, &Event::Received { bytes: data.len() }).await?;
+            }
+            Message::Text(text) if text == "end" => break,
+            Message::Text(_) => {
+                return Err(AppError::new(
+                    ErrorCode::InvalidRequest,
+                    "Expected binary messages, or end",
+                ))
+            }
+            Message::Close(_) => return Err(lost()),
+            Message::Ping(_) | Message::Pong(_) => {}
+        }
+    }
+
+    let data_raw = match decompress::is_gzip(&data) {
+        true => decompress::gunzip(&data, config.max_decompressed_bytes)?,
+        false => data,
+    };
+    let torrent = crate::parse_torrent(&data_raw, config)?;
+    send(
+        socket,
+        &Event::Name {
+            name: &torrent.info.name,
+        },
+    )
+    .await?;
+    let count = torrent.trackers().len();
+    send(socket, &Event::Trackers { count }).await?;
+    let files = Event::Files {
+        count: torrent.info.file_count(),
+        total_size: torrent.info.total_size(),
+    };
+    send(socket, &files).await?;
+
+    options.now = (!config.deterministic).then(crate::dates::now);
+    let inspection =
+        tokio::task::spawn_blocking(move || Inspection::new(torrent, &data_raw, &options))
+            .await
+            .map_err(|_| AppError::new(ErrorCode::InvalidMetainfo, "Failed to inspect torrent"))?;
+    let info_hash = &inspection.info_hash;
+    send(socket, &Event::Hashes { info_hash }).await?;
+    send(
+        socket,
+        &Event::Done {
+            inspection: &inspection,
+        },
+    )
+    .await
+}